@@ -0,0 +1,57 @@
+#![feature(test)]
+extern crate rogue_tutorial;
+extern crate test;
+
+use rand::prelude::*;
+use test::Bencher;
+
+use rogue_tutorial::data::structures::bit_matrix::BitMatrix;
+use rogue_tutorial::data::structures::matrix::*;
+
+const MAX_DIM: Dim = Dim {
+    width: 1024,
+    height: 1024,
+};
+
+fn random_fov() -> Matrix<bool> {
+    let mut rng = thread_rng();
+    Matrix::tabulate(MAX_DIM, |_| rng.gen_bool(0.1))
+}
+
+#[bench]
+fn big_bool_matrix_merge(b: &mut Bencher) {
+    let fov = random_fov();
+    let mut memory: Matrix<bool> = Matrix::new(MAX_DIM.width, MAX_DIM.height);
+
+    b.iter(|| {
+        for (p, v) in fov.iter() {
+            if *v {
+                memory[p] = true;
+            }
+        }
+    })
+}
+
+#[bench]
+fn big_bit_matrix_merge(b: &mut Bencher) {
+    let fov = BitMatrix::from(&random_fov());
+    let mut memory = BitMatrix::with_dim(MAX_DIM);
+
+    b.iter(|| {
+        memory.union_with(&fov);
+    })
+}
+
+#[bench]
+fn big_bool_matrix_creation(b: &mut Bencher) {
+    b.iter(|| {
+        let _: Matrix<bool> = Matrix::new(MAX_DIM.width, MAX_DIM.height);
+    })
+}
+
+#[bench]
+fn big_bit_matrix_creation(b: &mut Bencher) {
+    b.iter(|| {
+        let _ = BitMatrix::with_dim(MAX_DIM);
+    })
+}
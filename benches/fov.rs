@@ -4,14 +4,20 @@ extern crate test;
 
 use rand::prelude::*;
 use rogue_tutorial::common::fov::*;
+use rogue_tutorial::data::structures::bit_matrix::BitMatrix;
 use rogue_tutorial::data::structures::matrix::Matrix;
 use rogue_tutorial::data::structures::*;
 use test::Bencher;
 
+const LARGE_DIM: Dim = Dim {
+    width: 1024,
+    height: 1024,
+};
+
 fn random_level(d: Dim) -> LevelInfo {
     let mut rng = thread_rng();
     let m = Matrix::tabulate(d, |_| {
-        [TileType::Wall, TileType::RoomWall, TileType::Ground]
+        [TileId::WALL, TileId::ROOM_WALL, TileId::GROUND]
             .choose(&mut rng)
             .unwrap()
             .to_owned()
@@ -20,21 +26,22 @@ fn random_level(d: Dim) -> LevelInfo {
 }
 
 fn bench_fov(ld: &LevelInfo, radius: DimIndex, b: &mut Bencher) {
+    bench_fov_with(ld, radius, FovAlgorithm::default(), b);
+}
+
+fn bench_fov_with(ld: &LevelInfo, radius: DimIndex, algorithm: FovAlgorithm, b: &mut Bencher) {
     let pos = Pos {
         x: ld.width() / 2,
         y: ld.height() / 2,
     };
     b.iter(|| {
-        calculate_fov(ld, pos, radius);
+        calculate_fov_with(ld, pos, radius, algorithm);
     });
 }
 
 #[bench]
 fn fov_max(b: &mut Bencher) {
-    let ld = random_level(Dim {
-        width: DimIndex::max_value(),
-        height: DimIndex::max_value(),
-    });
+    let ld = random_level(LARGE_DIM);
     bench_fov(&ld, 30, b);
 }
 
@@ -48,6 +55,36 @@ fn fov_small(b: &mut Bencher) {
     bench_fov(&ld, 8, b);
 }
 
+#[bench]
+fn fov_small_shadowcasting(b: &mut Bencher) {
+    let ld = random_level(Dim {
+        width: 100,
+        height: 100,
+    });
+
+    bench_fov_with(&ld, 8, FovAlgorithm::Shadowcasting, b);
+}
+
+#[bench]
+fn fov_small_permissive(b: &mut Bencher) {
+    let ld = random_level(Dim {
+        width: 100,
+        height: 100,
+    });
+
+    bench_fov_with(&ld, 8, FovAlgorithm::Permissive, b);
+}
+
+#[bench]
+fn fov_small_raycast(b: &mut Bencher) {
+    let ld = random_level(Dim {
+        width: 100,
+        height: 100,
+    });
+
+    bench_fov_with(&ld, 8, FovAlgorithm::Raycast, b);
+}
+
 #[bench]
 fn fov_small_large_radius(b: &mut Bencher) {
     let ld = random_level(Dim {
@@ -60,10 +97,31 @@ fn fov_small_large_radius(b: &mut Bencher) {
 
 #[bench]
 fn fov_large_small_radius(b: &mut Bencher) {
-    let ld = random_level(Dim {
-        width: DimIndex::max_value(),
-        height: DimIndex::max_value(),
-    });
+    let ld = random_level(LARGE_DIM);
 
     bench_fov(&ld, 8, b);
 }
+
+fn bench_merge(ld: &LevelInfo, radius: DimIndex, b: &mut Bencher) {
+    let pos = Pos {
+        x: ld.width() / 2,
+        y: ld.height() / 2,
+    };
+    let fov = calculate_fov(ld, pos, radius);
+    let mut memory = BitMatrix::with_dim(ld.dim());
+    b.iter(|| fov.merge_into(&mut memory).unwrap());
+}
+
+#[bench]
+fn merge_small_window_into_memory(b: &mut Bencher) {
+    let ld = random_level(LARGE_DIM);
+
+    bench_merge(&ld, 8, b);
+}
+
+#[bench]
+fn merge_large_window_into_memory(b: &mut Bencher) {
+    let ld = random_level(LARGE_DIM);
+
+    bench_merge(&ld, 0, b);
+}
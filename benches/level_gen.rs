@@ -2,12 +2,18 @@
 extern crate rogue_tutorial;
 extern crate test;
 
-use rogue_tutorial::levels::level_1;
+use std::sync::Arc;
+
+use rogue_tutorial::data::structures::TileRegistry;
+use rogue_tutorial::levels::level_at;
+use rogue_tutorial::levels::vaults::builtin_vaults;
 use test::Bencher;
 
 #[bench]
 fn level_1_gen(b: &mut Bencher) {
     let mut rng = rand::thread_rng();
+    let tiles = TileRegistry::builtin();
+    let vaults = Arc::new(builtin_vaults(tiles.clone()));
 
-    b.iter(|| level_1(&mut rng))
+    b.iter(|| level_at(1, &mut rng, tiles.clone(), vaults.clone()))
 }
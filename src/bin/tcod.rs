@@ -5,16 +5,22 @@ extern crate rogue_tutorial;
 extern crate specs;
 extern crate tcod;
 
+use std::sync::Arc;
+
 use specs::{Builder, System, World};
 use tcod::colors::RED;
 use tcod::*;
 
 use rogue_tutorial::data::components::*;
 use rogue_tutorial::data::structures::*;
-use rogue_tutorial::levels::level_1;
+use rogue_tutorial::levels::vaults::{load_vaults, Vaults};
+use rogue_tutorial::levels::{level_at, populate};
 use rogue_tutorial::systems::render::Renderer;
 use rogue_tutorial::ui::Game;
 
+const TILES_PATH: &str = "static/tiles.toml";
+const VAULTS_PATH: &str = "static/vaults";
+
 const CONSOLE_DIM: Dim = Dim {
     width: 90,
     height: 50,
@@ -37,31 +43,41 @@ fn main() {
     let mut game = Game::new(world);
     let mut rng = rand::thread_rng();
     // FXIME Extract to script
-    let (level_info, level) = level_1(&mut rng);
+    let tiles = TileRegistry::load(TILES_PATH).unwrap_or_else(|e| panic!("{}: {}", TILES_PATH, e));
+    let vaults = load_vaults(VAULTS_PATH, tiles.clone())
+        .unwrap_or_else(|e| panic!("{}: {}", VAULTS_PATH, e));
+    let vaults = Arc::new(vaults);
+    let (level_info, level) = level_at(1, &mut rng, tiles, vaults.clone());
 
     game.world.add_resource(level_info);
+    game.world.add_resource(Vaults(vaults));
 
     game.world
         .create_entity()
         .is_player()
+        .with(EmitsLight::torch())
         .with_actor_components('@', RED, level.player_pos)
         .build();
 
-    for (monster, pos) in level.monsters {
-        game.world.create_entity().is_monster(&monster, pos).build();
-    }
+    populate(&mut game.world, &level);
 
     tcod::system::set_fps(LIMIT_FPS);
 
+    game.update();
     while !root.window_closed() {
-        game.update();
         game.render_on(&mut root);
 
         root.flush();
+        if game.is_busy() {
+            game.update();
+            continue;
+        }
         let x = root.wait_for_keypress(true);
         if let Some(c) = game.key_mapper.command(x) {
             trace!("{:?} - {:?}", x, c);
-            game.game_command_handler.exec(c, &mut game.world);
+            if game.game_command_handler.exec(c, &mut game.world) {
+                game.update();
+            }
         }
     }
 }
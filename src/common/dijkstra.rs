@@ -0,0 +1,233 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::Index;
+
+use crate::data::structures::matrix::Matrix;
+use crate::data::structures::pos::PosCollection;
+use crate::data::structures::ALL_DIRS;
+use crate::data::structures::*;
+
+pub type Distance = i32;
+
+const UNREACHABLE: Distance = Distance::max_value();
+
+/// Distance from the closest of several goals to every walkable cell, 8-connected.
+/// Walking `downhill` from any cell leads to a goal
+#[derive(Debug, Clone)]
+pub struct DijkstraMap {
+    distances: Matrix<Distance>,
+}
+
+impl DijkstraMap {
+    /// Stepping onto a cell costs its `movement_cost`
+    pub fn new<M, C, P>(m: &M, sources: &P) -> Self
+    where
+        C: CellObject,
+        M: Index<Pos, Output = C> + HasDim,
+        P: PosCollection,
+    {
+        Self::weighted(m, sources, |_, to| m[to].movement_cost() as Distance)
+    }
+
+    /// Like `new`, but stepping between two adjacent cells costs `step_cost`, which must be positive
+    pub fn weighted<M, C, P, F>(m: &M, sources: &P, step_cost: F) -> Self
+    where
+        C: CellObject,
+        M: Index<Pos, Output = C> + HasDim,
+        P: PosCollection,
+        F: Fn(Pos, Pos) -> Distance,
+    {
+        let mut distances = Matrix::tabulate(
+            Dim {
+                width: m.width(),
+                height: m.height(),
+            },
+            |_| UNREACHABLE,
+        );
+        for p in sources.iter_pos() {
+            if m.is_valid(p) && m[p].is_walkable() {
+                distances[p] = 0;
+            }
+        }
+        let mut map = DijkstraMap { distances };
+        map.relax(m, step_cost);
+        map
+    }
+
+    /// Propagates distances until every cell is at most a step away from a lower neighbour
+    fn relax<M, C, F>(&mut self, m: &M, step_cost: F)
+    where
+        C: CellObject,
+        M: Index<Pos, Output = C> + HasDim,
+        F: Fn(Pos, Pos) -> Distance,
+    {
+        let mut open: BinaryHeap<_> = self
+            .distances
+            .iter()
+            .filter(|(_, &d)| d != UNREACHABLE)
+            .map(|(p, &d)| Reverse((d, p)))
+            .collect();
+
+        while let Some(Reverse((distance, current))) = open.pop() {
+            if self.distances[current] < distance {
+                continue;
+            }
+            for &dir in ALL_DIRS.iter() {
+                let next = match m.step(current, dir) {
+                    Some(next) => next,
+                    None => continue,
+                };
+                if !m[next].is_walkable() {
+                    continue;
+                }
+                let next_distance = distance + step_cost(current, next);
+                if next_distance < self.distances[next] {
+                    self.distances[next] = next_distance;
+                    open.push(Reverse((next_distance, next)));
+                }
+            }
+        }
+    }
+
+    /// Map leading away from the goals. Distances are scaled by `-factor` and relaxed again,
+    /// so a factor above 1 lets fleeing monsters pass by a goal to reach a farther escape
+    pub fn inverted<M, C>(&self, m: &M, factor: f32) -> Self
+    where
+        C: CellObject,
+        M: Index<Pos, Output = C> + HasDim,
+    {
+        let distances = self.distances.map(|&d| {
+            if d == UNREACHABLE {
+                UNREACHABLE
+            } else {
+                (d as f32 * -factor).round() as Distance
+            }
+        });
+        let mut map = DijkstraMap { distances };
+        map.relax(m, |_, _| 1);
+        map
+    }
+
+    pub fn distance(&self, pos: Pos) -> Option<Distance> {
+        if self.distances.is_valid(pos) && self.distances[pos] != UNREACHABLE {
+            Some(self.distances[pos])
+        } else {
+            None
+        }
+    }
+
+    /// Neighbour with the lowest distance, if it is lower than at `pos`
+    pub fn downhill(&self, pos: Pos) -> Option<Pos> {
+        let current = self.distance(pos)?;
+        self.distances
+            .neighbors8(pos)
+            .filter_map(|n| self.distance(n).map(|d| (d, n)))
+            .filter(|&(d, _)| d < current)
+            .min()
+            .map(|(_, n)| n)
+    }
+
+    /// Reachable cell farthest from every goal
+    pub fn farthest(&self) -> Option<Pos> {
+        self.distances
+            .iter()
+            .filter(|(_, &d)| d != UNREACHABLE)
+            .max_by_key(|(p, &d)| (d, Reverse(*p)))
+            .map(|(p, _)| p)
+    }
+}
+
+impl HasDim for DijkstraMap {
+    fn width(&self) -> DimIndex {
+        self.distances.width()
+    }
+
+    fn height(&self) -> DimIndex {
+        self.distances.height()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+    use proptest::{prop_assert, prop_assert_eq, proptest, proptest_helper};
+
+    use crate::common::path::AStar;
+    use crate::data::structures::pos::test::*;
+
+    use super::*;
+
+    fn open_level(width: DimIndex, height: DimIndex) -> LevelInfo {
+        LevelInfo::from_matrix(Matrix::tabulate(Dim { width, height }, |_| TileId::GROUND))
+    }
+
+    fn level_and_positions() -> impl Strategy<Value = (LevelInfo, Pos, Pos)> {
+        (1..24 as DimIndex, 1..24 as DimIndex).prop_flat_map(|(width, height)| {
+            let level = open_level(width, height);
+            let dim = level.dim();
+            (Just(level), pos_in_dim(dim), pos_in_dim(dim))
+        })
+    }
+
+    #[test]
+    fn seeds_from_many_sources() {
+        let level = open_level(7, 1);
+        let sources = vec![Pos { x: 0, y: 0 }, Pos { x: 6, y: 0 }];
+        let map = DijkstraMap::new(&level, &sources);
+        let distances: Vec<_> = (0..7)
+            .map(|x| map.distance(Pos { x, y: 0 }).unwrap())
+            .collect();
+        assert_eq!(distances, vec![0, 1, 2, 3, 2, 1, 0]);
+        assert_eq!(map.farthest(), Some(Pos { x: 3, y: 0 }));
+    }
+
+    #[test]
+    fn walls_are_unreachable() {
+        let mut level = open_level(3, 1);
+        level.set(Pos { x: 1, y: 0 }, TileId::WALL);
+        let map = DijkstraMap::new(&level, &vec![Pos { x: 0, y: 0 }]);
+        assert_eq!(map.distance(Pos { x: 1, y: 0 }), None);
+        assert_eq!(map.distance(Pos { x: 2, y: 0 }), None);
+        assert_eq!(map.downhill(Pos { x: 2, y: 0 }), None);
+    }
+
+    #[test]
+    fn weighted_costs_slow_down_propagation() {
+        let level = open_level(3, 1);
+        let map = DijkstraMap::weighted(&level, &vec![Pos { x: 0, y: 0 }], |_, _| 5);
+        assert_eq!(map.distance(Pos { x: 2, y: 0 }), Some(10));
+    }
+
+    #[test]
+    fn inverted_map_leads_away() {
+        let level = open_level(9, 1);
+        let player = Pos { x: 2, y: 0 };
+        let flee = DijkstraMap::new(&level, &player).inverted(&level, 1.2);
+        assert_eq!(flee.downhill(Pos { x: 3, y: 0 }), Some(Pos { x: 4, y: 0 }));
+        assert_eq!(flee.downhill(Pos { x: 8, y: 0 }), None);
+    }
+
+    proptest! {
+        #[test]
+        fn downhill_reaches_goal((level, goal, start) in level_and_positions()) {
+            let map = DijkstraMap::new(&level, &goal);
+            let mut current = start;
+            let mut steps = 0;
+            while let Some(next) = map.downhill(current) {
+                prop_assert_eq!(current.chebyshev_distance(next), 1);
+                current = next;
+                steps += 1;
+            }
+            prop_assert_eq!(current, goal);
+            prop_assert_eq!(Some(steps), map.distance(start));
+        }
+
+        #[test]
+        fn agrees_with_astar((level, goal, start) in level_and_positions()) {
+            let map = DijkstraMap::new(&level, &vec![goal]);
+            let path = AStar::new().with_diagonals(true).find(&level, start, goal);
+            prop_assert!(path.is_some());
+            prop_assert_eq!(map.distance(start), path.map(|p| p.cost as Distance));
+        }
+    }
+}
@@ -1,50 +1,825 @@
-use crate::data::structures::matrix::Matrix;
-use crate::data::structures::pos::PosCollection;
-use crate::data::structures::*;
+use std::cmp::{max, min};
 use std::ops::Index;
-use tcod::map::*;
 
-fn into_fov_map<M, C>(m: &M) -> Map
+use crate::common::los::has_line_of_sight;
+use crate::data::structures::bit_matrix::BitMatrix;
+use crate::data::structures::rect::Rect;
+use crate::data::structures::*;
+
+/// How light spreads from the viewer. All of them light up walls that stop it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FovAlgorithm {
+    /// Recursive shadowcasting by octants, fast but not symmetric
+    Shadowcasting,
+    /// Symmetric shadowcasting by quadrants: if A sees floor B, B sees A
+    Symmetric,
+    /// Precise permissive FOV: a tile is seen when any line from the viewer's tile to it is clear.
+    /// Sees the most of all of them, and is symmetric as well
+    Permissive,
+    /// Tile is seen when either Bresenham line between viewer and tile is clear, see `common::los`.
+    /// Casts a line per tile, so it's the slowest one
+    Raycast,
+}
+
+impl Default for FovAlgorithm {
+    fn default() -> Self {
+        FovAlgorithm::Symmetric
+    }
+}
+
+/// Visible part of the level around a viewer. Only the bounding box of the sight radius is
+/// stored, positions are given in level coordinates and everything outside of it is unseen
+#[derive(Debug, Clone, PartialEq)]
+pub struct FovWindow {
+    origin: Pos,
+    area: BitMatrix,
+    level_dim: Dim,
+}
+
+impl FovWindow {
+    fn new(bounds: Rect, level_dim: Dim) -> Self {
+        FovWindow {
+            origin: bounds.from,
+            area: BitMatrix::with_dim(bounds.dim()),
+            level_dim,
+        }
+    }
+
+    /// Level position of the top left corner of the window
+    pub fn origin(&self) -> Pos {
+        self.origin
+    }
+
+    /// Visibility inside of the window, relative to `origin`
+    pub fn area(&self) -> &BitMatrix {
+        &self.area
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::with_dim(self.origin, self.area.dim())
+    }
+
+    pub fn get(&self, pos: Pos) -> bool {
+        self.bounds().contains(pos) && self.area.get(self.to_local(pos))
+    }
+
+    fn set(&mut self, pos: Pos, value: bool) {
+        let local = self.to_local(pos);
+        self.area.set(local, value);
+    }
+
+    fn to_local(&self, pos: Pos) -> Pos {
+        Pos {
+            x: pos.x - self.origin.x,
+            y: pos.y - self.origin.y,
+        }
+    }
+
+    /// Level positions of all visible tiles
+    pub fn ones(&self) -> impl Iterator<Item = Pos> + '_ {
+        let origin = self.origin;
+        self.area.ones().map(move |p| Pos {
+            x: p.x + origin.x,
+            y: p.y + origin.y,
+        })
+    }
+
+    /// Hides visible tiles for which `f` returns false
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(Pos) -> bool,
+    {
+        let hidden: Vec<Pos> = self.ones().filter(|&p| !f(p)).collect();
+        for p in hidden {
+            self.set(p, false);
+        }
+    }
+
+    /// Marks all visible tiles in `memory`, which covers the whole level. Memory of a level with
+    /// other dimensions is left as it is
+    pub fn merge_into(&self, memory: &mut BitMatrix) -> Result<(), DimMismatch> {
+        if memory.dim() != self.level_dim {
+            return Err(DimMismatch {
+                memory: memory.dim(),
+                level: self.level_dim,
+            });
+        }
+        memory.union_at(&self.area, self.origin);
+        Ok(())
+    }
+}
+
+/// Memory given to `FovWindow::merge_into` is of another level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DimMismatch {
+    pub memory: Dim,
+    pub level: Dim,
+}
+
+impl Index<Pos> for FovWindow {
+    type Output = bool;
+
+    fn index(&self, pos: Pos) -> &bool {
+        if self.get(pos) {
+            &true
+        } else {
+            &false
+        }
+    }
+}
+
+/// Dimensions of the level the window was calculated for
+impl HasDim for FovWindow {
+    fn width(&self) -> DimIndex {
+        self.level_dim.width
+    }
+
+    fn height(&self) -> DimIndex {
+        self.level_dim.height
+    }
+}
+
+/// Cells seen from `pos`. A radius of 0 means that sight is limited only by the level itself
+pub fn calculate_fov<M, C>(m: &M, pos: Pos, sight_radius: DimIndex) -> FovWindow
+where
+    C: CellObject,
+    M: Index<Pos, Output = C> + HasDim,
+{
+    calculate_fov_with(m, pos, sight_radius, FovAlgorithm::default())
+}
+
+/// Only tiles within the bounding box of `sight_radius` are ever looked at
+pub fn calculate_fov_with<M, C>(
+    m: &M,
+    pos: Pos,
+    sight_radius: DimIndex,
+    algorithm: FovAlgorithm,
+) -> FovWindow
 where
     C: CellObject,
     M: Index<Pos, Output = C> + HasDim,
 {
-    let mut map = Map::new(m.width().into(), m.height().into());
-    for p in (Pos::default()..=m.max_pos()).iter_pos() {
-        map.set(
-            p.x.into(),
-            p.y.into(),
-            !m[p].blocks_sight(),
-            m[p].is_walkable(),
+    let level = Rect::with_dim(Pos::default(), m.dim());
+    if !m.is_valid(pos) {
+        let empty = Dim {
+            width: 0,
+            height: 0,
+        };
+        return FovWindow::new(Rect::with_dim(pos, empty), m.dim());
+    }
+    let bounds = if sight_radius == 0 {
+        level
+    } else {
+        Rect::with_dim(
+            pos,
+            Dim {
+                width: 1,
+                height: 1,
+            },
         )
+        .inflate(sight_radius)
+        .overlap(&level)
+        .expect("calculate_fov_with bounds")
+    };
+    let radius = if sight_radius == 0 {
+        // Far enough to reach every corner
+        i32::from(m.width()) + i32::from(m.height())
+    } else {
+        i32::from(sight_radius)
+    };
+    let mut fov = FovWindow::new(bounds, m.dim());
+    let mut view = View {
+        m,
+        fov: &mut fov,
+        origin: (i32::from(pos.x), i32::from(pos.y)),
+        radius,
+    };
+    view.reveal(view.origin);
+    match algorithm {
+        FovAlgorithm::Shadowcasting => view.shadowcasting(),
+        FovAlgorithm::Symmetric => view.symmetric(),
+        FovAlgorithm::Permissive => view.permissive(),
+        FovAlgorithm::Raycast => view.raycast(),
     }
-    map
+    fov
 }
 
-fn from_fov_map(fov_map: Map) -> Matrix<bool> {
-    let (width, height) = fov_map.size();
-    let dim = Dim {
-        width: width as DimIndex,
-        height: height as DimIndex,
-    };
-    Matrix::tabulate(dim, |pos| fov_map.is_in_fov(pos.x.into(), pos.y.into()))
+struct View<'a, M> {
+    m: &'a M,
+    fov: &'a mut FovWindow,
+    origin: (i32, i32),
+    radius: i32,
+}
+
+/// Transformations from the first octant to all the others
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+/// Slope `num / den` of a symmetric shadowcasting row boundary, `den` is always positive
+#[derive(Debug, Clone, Copy)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    depth: i32,
+    start: Slope,
+    end: Slope,
 }
 
-pub fn calculate_fov<M, C>(m: &M, pos: Pos, sight_radius: DimIndex) -> Matrix<bool>
+impl Row {
+    fn min_col(&self) -> i32 {
+        // round half up of depth * start
+        floor_div(
+            2 * self.depth * self.start.num + self.start.den,
+            2 * self.start.den,
+        )
+    }
+
+    fn max_col(&self) -> i32 {
+        // round half down of depth * end
+        -floor_div(
+            -2 * self.depth * self.end.num + self.end.den,
+            2 * self.end.den,
+        )
+    }
+
+    fn is_symmetric(&self, col: i32) -> bool {
+        col * self.start.den >= self.depth * self.start.num
+            && col * self.end.den <= self.depth * self.end.num
+    }
+
+    fn next(&self) -> Row {
+        Row {
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+}
+
+fn floor_div(a: i32, b: i32) -> i32 {
+    let d = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) {
+        d - 1
+    } else {
+        d
+    }
+}
+
+/// Slope of the left edge of a tile at `col` in row `depth`
+fn tile_slope(depth: i32, col: i32) -> Slope {
+    Slope {
+        num: 2 * col - 1,
+        den: 2 * depth,
+    }
+}
+
+/// Line through two tile corners of a permissive FOV quadrant. A point is below the line when
+/// `relative_slope` is positive, which is on the side of the quadrant's y axis
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    from: (i32, i32),
+    to: (i32, i32),
+}
+
+impl Line {
+    /// Taken in `i64`, corners of large levels overflow `i32`
+    fn relative_slope(&self, (x, y): (i32, i32)) -> i64 {
+        let dx = i64::from(self.to.0 - self.from.0);
+        let dy = i64::from(self.to.1 - self.from.1);
+        dy * i64::from(self.to.0 - x) - dx * i64::from(self.to.1 - y)
+    }
+
+    fn is_below(&self, p: (i32, i32)) -> bool {
+        self.relative_slope(p) > 0
+    }
+
+    fn is_below_or_collinear(&self, p: (i32, i32)) -> bool {
+        self.relative_slope(p) >= 0
+    }
+
+    fn is_above(&self, p: (i32, i32)) -> bool {
+        self.relative_slope(p) < 0
+    }
+
+    fn is_above_or_collinear(&self, p: (i32, i32)) -> bool {
+        self.relative_slope(p) <= 0
+    }
+
+    fn is_collinear(&self, p: (i32, i32)) -> bool {
+        self.relative_slope(p) == 0
+    }
+
+    fn is_collinear_with(&self, other: &Line) -> bool {
+        self.is_collinear(other.from) && self.is_collinear(other.to)
+    }
+}
+
+/// Wall corner a view line was bent around. Bumps of one side of a view are chained through
+/// `parent`, views split from each other share the bumps they had before the split
+#[derive(Debug, Clone, Copy)]
+struct Bump {
+    at: (i32, i32),
+    parent: Option<usize>,
+}
+
+/// Part of a quadrant still visible between two lines, bumps index `PermissiveQuadrant::bumps`
+#[derive(Debug, Clone)]
+struct PermissiveView {
+    shallow: Line,
+    steep: Line,
+    shallow_bump: Option<usize>,
+    steep_bump: Option<usize>,
+}
+
+/// Views of a quadrant ordered from the shallowest to the steepest
+struct PermissiveQuadrant {
+    views: Vec<PermissiveView>,
+    bumps: Vec<Bump>,
+}
+
+impl PermissiveQuadrant {
+    fn new(extent_x: i32, extent_y: i32) -> Self {
+        PermissiveQuadrant {
+            views: vec![PermissiveView {
+                shallow: Line {
+                    from: (0, 1),
+                    to: (extent_x, 0),
+                },
+                steep: Line {
+                    from: (1, 0),
+                    to: (0, extent_y),
+                },
+                shallow_bump: None,
+                steep_bump: None,
+            }],
+            bumps: vec![],
+        }
+    }
+
+    /// View the tile at `(x, y)` of the quadrant is seen through
+    fn view_of(&self, (x, y): (i32, i32)) -> Option<usize> {
+        let index = self
+            .views
+            .iter()
+            .position(|v| !v.steep.is_below_or_collinear((x + 1, y)))?;
+        if self.views[index].shallow.is_above_or_collinear((x, y + 1)) {
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    /// Narrows the view around a wall at `(x, y)`, splitting it when the wall is in the middle
+    fn block(&mut self, index: usize, (x, y): (i32, i32)) {
+        let (top_left, bottom_right) = ((x, y + 1), (x + 1, y));
+        let view = &self.views[index];
+        let cuts_shallow = view.shallow.is_above(bottom_right);
+        let cuts_steep = view.steep.is_below(top_left);
+        match (cuts_shallow, cuts_steep) {
+            (true, true) => {
+                self.views.remove(index);
+            }
+            (true, false) => {
+                self.add_shallow_bump(index, top_left);
+                self.check_view(index);
+            }
+            (false, true) => {
+                self.add_steep_bump(index, bottom_right);
+                self.check_view(index);
+            }
+            (false, false) => {
+                let copy = self.views[index].clone();
+                self.views.insert(index, copy);
+                self.add_steep_bump(index, bottom_right);
+                let steep = if self.check_view(index) {
+                    index + 1
+                } else {
+                    index
+                };
+                self.add_shallow_bump(steep, top_left);
+                self.check_view(steep);
+            }
+        }
+    }
+
+    fn add_shallow_bump(&mut self, index: usize, at: (i32, i32)) {
+        let (view, bumps) = (&mut self.views[index], &mut self.bumps);
+        view.shallow.to = at;
+        bumps.push(Bump {
+            at,
+            parent: view.shallow_bump,
+        });
+        view.shallow_bump = Some(bumps.len() - 1);
+        let mut current = view.steep_bump;
+        while let Some(bump) = current.map(|i| bumps[i]) {
+            if view.shallow.is_above(bump.at) {
+                view.shallow.from = bump.at;
+            }
+            current = bump.parent;
+        }
+    }
+
+    fn add_steep_bump(&mut self, index: usize, at: (i32, i32)) {
+        let (view, bumps) = (&mut self.views[index], &mut self.bumps);
+        view.steep.to = at;
+        bumps.push(Bump {
+            at,
+            parent: view.steep_bump,
+        });
+        view.steep_bump = Some(bumps.len() - 1);
+        let mut current = view.shallow_bump;
+        while let Some(bump) = current.map(|i| bumps[i]) {
+            if view.steep.is_below(bump.at) {
+                view.steep.from = bump.at;
+            }
+            current = bump.parent;
+        }
+    }
+
+    /// Drops the view when it has narrowed down to a line along the quadrant's edge.
+    /// Tells whether it's still there
+    fn check_view(&mut self, index: usize) -> bool {
+        let view = &self.views[index];
+        let closed = view.shallow.is_collinear_with(&view.steep)
+            && (view.shallow.is_collinear((0, 1)) || view.shallow.is_collinear((1, 0)));
+        if closed {
+            self.views.remove(index);
+        }
+        !closed
+    }
+}
+
+impl<'a, M, C> View<'a, M>
 where
     C: CellObject,
     M: Index<Pos, Output = C> + HasDim,
 {
-    // TODO(#24) - There's a possibility to have smaller memory pressure to calculate fov only
-    // for what's in radius
-    let mut fov_map = into_fov_map(m);
-    fov_map.compute_fov(
-        pos.x.into(),
-        pos.y.into(),
-        sight_radius.into(),
-        true,
-        FovAlgorithm::Basic,
-    );
-
-    from_fov_map(fov_map)
+    fn to_pos(&self, (x, y): (i32, i32)) -> Option<Pos> {
+        if x < 0 || y < 0 || x >= i32::from(self.m.width()) || y >= i32::from(self.m.height()) {
+            None
+        } else {
+            Some(Pos {
+                x: x as DimIndex,
+                y: y as DimIndex,
+            })
+        }
+    }
+
+    /// Outside of the level counts as a wall
+    fn blocks(&self, p: (i32, i32)) -> bool {
+        self.to_pos(p).map_or(true, |p| self.m[p].blocks_sight())
+    }
+
+    /// Squares are taken in `i64`, they don't fit `i32` for radiuses of large levels
+    fn in_radius(&self, (x, y): (i32, i32)) -> bool {
+        let dx = i64::from(x - self.origin.0);
+        let dy = i64::from(y - self.origin.1);
+        let r = i64::from(self.radius);
+        dx * dx + dy * dy <= r * r
+    }
+
+    fn reveal(&mut self, p: (i32, i32)) {
+        if self.in_radius(p) {
+            if let Some(p) = self.to_pos(p) {
+                self.fov.set(p, true);
+            }
+        }
+    }
+
+    fn shadowcasting(&mut self) {
+        for &octant in OCTANTS.iter() {
+            self.cast_light(1, 1.0, 0.0, octant);
+        }
+    }
+
+    fn cast_light(&mut self, row: i32, mut start: f64, end: f64, octant: (i32, i32, i32, i32)) {
+        if start < end {
+            return;
+        }
+        let (xx, xy, yx, yy) = octant;
+        let mut new_start = 0.0;
+        for j in row..=self.radius {
+            let dy = -j;
+            let mut blocked = false;
+            for dx in -j..=0 {
+                let left_slope = (f64::from(dx) - 0.5) / (f64::from(dy) + 0.5);
+                let right_slope = (f64::from(dx) + 0.5) / (f64::from(dy) - 0.5);
+                if start < right_slope {
+                    continue;
+                } else if end > left_slope {
+                    break;
+                }
+                let p = (
+                    self.origin.0 + dx * xx + dy * xy,
+                    self.origin.1 + dx * yx + dy * yy,
+                );
+                self.reveal(p);
+                if blocked {
+                    if self.blocks(p) {
+                        new_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = new_start;
+                    }
+                } else if self.blocks(p) && j < self.radius {
+                    blocked = true;
+                    self.cast_light(j + 1, start, left_slope, octant);
+                    new_start = right_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+
+    fn symmetric(&mut self) {
+        for &(dir_x, dir_y) in [(0, -1), (1, 0), (0, 1), (-1, 0)].iter() {
+            let first = Row {
+                depth: 1,
+                start: Slope { num: -1, den: 1 },
+                end: Slope { num: 1, den: 1 },
+            };
+            let mut rows = vec![first];
+            while let Some(mut row) = rows.pop() {
+                if row.depth > self.radius {
+                    continue;
+                }
+                let (x, y) = self.origin;
+                let quadrant = |depth: i32, col: i32| {
+                    if dir_x == 0 {
+                        (x + col, y + dir_y * depth)
+                    } else {
+                        (x + dir_x * depth, y + col)
+                    }
+                };
+                let mut prev_wall: Option<bool> = None;
+                for col in row.min_col()..=row.max_col() {
+                    let p = quadrant(row.depth, col);
+                    let wall = self.blocks(p);
+                    if wall || row.is_symmetric(col) {
+                        self.reveal(p);
+                    }
+                    if prev_wall == Some(true) && !wall {
+                        row.start = tile_slope(row.depth, col);
+                    }
+                    if prev_wall == Some(false) && wall {
+                        let mut next = row.next();
+                        next.end = tile_slope(row.depth, col);
+                        rows.push(next);
+                    }
+                    prev_wall = Some(wall);
+                }
+                if prev_wall == Some(false) {
+                    rows.push(row.next());
+                }
+            }
+        }
+    }
+
+    /// Walks every quadrant by diagonals going away from the viewer. Each quadrant reaches at
+    /// least one tile past the level, which is a wall, so that rows and columns of the viewer
+    /// are never at its very edge
+    fn permissive(&mut self) {
+        let (x, y) = self.origin;
+        let extent = |to_edge: i32| max(1, min(to_edge, self.radius));
+        let (left, right) = (extent(x), extent(i32::from(self.m.width()) - x - 1));
+        let (up, down) = (extent(y), extent(i32::from(self.m.height()) - y - 1));
+        for &(dir, extent) in [
+            ((1, 1), (right, down)),
+            ((1, -1), (right, up)),
+            ((-1, -1), (left, up)),
+            ((-1, 1), (left, down)),
+        ]
+        .iter()
+        {
+            self.permissive_quadrant(dir, extent);
+        }
+    }
+
+    fn permissive_quadrant(
+        &mut self,
+        (dir_x, dir_y): (i32, i32),
+        (extent_x, extent_y): (i32, i32),
+    ) {
+        let mut quadrant = PermissiveQuadrant::new(extent_x, extent_y);
+        for i in 1..=extent_x + extent_y {
+            for j in max(0, i - extent_x)..=min(i, extent_y) {
+                if quadrant.views.is_empty() {
+                    return;
+                }
+                let local = (i - j, j);
+                let index = match quadrant.view_of(local) {
+                    Some(index) => index,
+                    None => continue,
+                };
+                let p = (
+                    self.origin.0 + local.0 * dir_x,
+                    self.origin.1 + local.1 * dir_y,
+                );
+                self.reveal(p);
+                if self.blocks(p) {
+                    quadrant.block(index, local);
+                }
+            }
+        }
+    }
+
+    /// Casts a line to every tile of the window, the window is already cut to the level
+    fn raycast(&mut self) {
+        let origin = self.origin;
+        let from = self.to_pos(origin).expect("View::raycast origin");
+        for to in self.fov.bounds().iter() {
+            let p = (i32::from(to.x), i32::from(to.y));
+            if p != origin && self.in_radius(p) && has_line_of_sight(self.m, from, to) {
+                self.reveal(p);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+    use proptest::{
+        prop_assert, prop_assert_eq, prop_assume, prop_oneof, proptest, proptest_helper,
+    };
+
+    use crate::data::structures::matrix::Matrix;
+    use crate::data::structures::pos::PosCollection;
+    use crate::levels::ascii::AsciiMap;
+
+    use super::*;
+
+    const ALGORITHMS: [FovAlgorithm; 4] = [
+        FovAlgorithm::Shadowcasting,
+        FovAlgorithm::Symmetric,
+        FovAlgorithm::Permissive,
+        FovAlgorithm::Raycast,
+    ];
+
+    fn algorithm() -> impl Strategy<Value = FovAlgorithm> {
+        prop_oneof![
+            Just(FovAlgorithm::Shadowcasting),
+            Just(FovAlgorithm::Symmetric),
+            Just(FovAlgorithm::Permissive),
+            Just(FovAlgorithm::Raycast),
+        ]
+    }
+
+    /// Small random levels with a few walls and a position inside
+    fn level_and_pos() -> BoxedStrategy<(LevelInfo, Pos)> {
+        (2..20 as DimIndex, 2..20 as DimIndex)
+            .prop_flat_map(|(width, height)| {
+                let size = width as usize * height as usize;
+                (
+                    proptest::collection::vec(proptest::bool::weighted(0.25), size),
+                    0..width,
+                    0..height,
+                )
+                    .prop_map(move |(walls, x, y)| {
+                        let m = Matrix::tabulate(Dim { width, height }, |p| {
+                            if walls[p.x as usize + p.y as usize * width as usize] {
+                                TileId::WALL
+                            } else {
+                                TileId::GROUND
+                            }
+                        });
+                        (LevelInfo::from_matrix(m), Pos { x, y })
+                    })
+            })
+            .boxed()
+    }
+
+    fn open_level(width: DimIndex, height: DimIndex) -> LevelInfo {
+        LevelInfo::from_matrix(Matrix::tabulate(Dim { width, height }, |_| TileId::GROUND))
+    }
+
+    #[test]
+    fn wall_hides_what_is_behind() {
+        let map = AsciiMap::parse(
+            "
+            .....#...
+            .....#...
+            .....#...
+            .....#...
+            ...@.#...
+            .....#...
+            .....#...
+            .....#...
+            .....#...
+            ",
+        )
+        .unwrap();
+        let origin = map.player_pos().unwrap();
+        for &algorithm in ALGORITHMS.iter() {
+            let fov = calculate_fov_with(&map.level, origin, 8, algorithm);
+            assert!(fov[Pos { x: 5, y: 4 }], "{:?} misses the wall", algorithm);
+            assert!(fov[Pos { x: 4, y: 4 }], "{:?} misses the floor", algorithm);
+            for p in fov.ones() {
+                assert!(p.x <= 5, "{:?} sees {:?} behind the wall", algorithm, p);
+            }
+        }
+    }
+
+    #[test]
+    fn pillar_casts_shadow() {
+        let map = AsciiMap::parse("@.#......").unwrap();
+        for &algorithm in ALGORITHMS.iter() {
+            let fov = calculate_fov_with(&map.level, Pos { x: 0, y: 0 }, 8, algorithm);
+            assert!(fov[Pos { x: 2, y: 0 }]);
+            assert!(!fov[Pos { x: 3, y: 0 }], "{:?} sees through", algorithm);
+        }
+    }
+
+    #[test]
+    fn huge_radius_does_not_overflow() {
+        let level = open_level(3, 2);
+        for &algorithm in ALGORITHMS.iter() {
+            let fov = calculate_fov_with(&level, Pos { x: 0, y: 0 }, 50_000, algorithm);
+            assert_eq!(fov.ones().count(), 6, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn window_covers_only_sight_radius() {
+        let level = open_level(50, 50);
+        let fov = calculate_fov(&level, Pos { x: 2, y: 30 }, 8);
+        assert_eq!(
+            fov.bounds(),
+            Rect::inclusive(Pos { x: 0, y: 22 }, Pos { x: 10, y: 38 })
+        );
+        assert!(fov[Pos { x: 2, y: 38 }]);
+        assert!(!fov[Pos { x: 2, y: 39 }]);
+        assert_eq!(fov.dim(), level.dim());
+
+        let mut memory = BitMatrix::with_dim(level.dim());
+        fov.merge_into(&mut memory).unwrap();
+        assert_eq!(
+            memory.ones().collect::<Vec<_>>(),
+            fov.ones().collect::<Vec<_>>()
+        );
+        let mut other_level = BitMatrix::new(50, 49);
+        assert!(fov.merge_into(&mut other_level).is_err());
+        assert_eq!(other_level.count_ones(), 0);
+    }
+
+    proptest! {
+        #[test]
+        fn open_area_is_visible_within_radius(
+            width in 1..30 as DimIndex,
+            height in 1..30 as DimIndex,
+            radius in 1..10 as DimIndex,
+            algorithm in algorithm(),
+        ) {
+            let level = open_level(width, height);
+            let origin = Pos { x: width / 2, y: height / 2 };
+            let fov = calculate_fov_with(&level, origin, radius, algorithm);
+            for p in level.iter_pos() {
+                let d = origin.euclidean_distance(p);
+                prop_assert_eq!(fov[p], d <= f64::from(radius), "{:?} at {:?}", algorithm, p);
+            }
+        }
+
+        #[test]
+        fn unlimited_radius_sees_whole_open_level(width in 1..30 as DimIndex, height in 1..30 as DimIndex) {
+            let level = open_level(width, height);
+            let fov = calculate_fov(&level, Pos { x: 0, y: 0 }, 0);
+            prop_assert_eq!(fov.ones().count(), width as usize * height as usize);
+        }
+
+        #[test]
+        fn viewer_always_sees_itself((level, pos) in level_and_pos(), algorithm in algorithm()) {
+            let fov = calculate_fov_with(&level, pos, 8, algorithm);
+            prop_assert!(fov[pos]);
+        }
+
+        #[test]
+        fn symmetric_algorithms_are_symmetric(
+            (level, pos) in level_and_pos(),
+            symmetric in prop_oneof![
+                Just(FovAlgorithm::Symmetric),
+                Just(FovAlgorithm::Permissive),
+                Just(FovAlgorithm::Raycast),
+            ],
+        ) {
+            prop_assume!(!level[pos].blocks_sight());
+            let fov = calculate_fov_with(&level, pos, 8, symmetric);
+            for other in fov.ones().filter(|&p| !level[p].blocks_sight()) {
+                let back = calculate_fov_with(&level, other, 8, symmetric);
+                prop_assert!(back[pos], "{:?} sees {:?} but not back", pos, other);
+            }
+        }
+    }
 }
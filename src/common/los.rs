@@ -0,0 +1,180 @@
+use std::ops::Index;
+
+use crate::data::structures::line::Line;
+use crate::data::structures::*;
+
+/// Path a projectile takes from `from` towards `to`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineOfFire {
+    /// Positions the projectile passes, without the start. Ends at `to` or where it was stopped
+    pub path: Vec<Pos>,
+    /// First position between the ends that blocks sight, or the last one on the map
+    pub blocked_by: Option<Pos>,
+}
+
+impl LineOfFire {
+    pub fn is_clear(&self) -> bool {
+        self.blocked_by.is_none()
+    }
+}
+
+/// Whether `to` can be seen from `from`. Same as raycast FOV, so it's symmetric and a wall at
+/// `to` can be seen as well
+pub fn has_line_of_sight<M, C>(m: &M, from: Pos, to: Pos) -> bool
+where
+    C: CellObject,
+    M: Index<Pos, Output = C> + HasDim,
+{
+    m.is_valid(from)
+        && m.is_valid(to)
+        && (first_blocker(m, from, to).is_none() || first_blocker(m, to, from).is_none())
+}
+
+/// Straight path from `from` to `to`, stopped by the first tile that blocks sight or by the
+/// edge of the map. A shot from outside of the map goes nowhere
+pub fn line_of_fire<M, C>(m: &M, from: Pos, to: Pos) -> LineOfFire
+where
+    C: CellObject,
+    M: Index<Pos, Output = C> + HasDim,
+{
+    if !m.is_valid(from) {
+        return LineOfFire {
+            path: vec![],
+            blocked_by: Some(from),
+        };
+    }
+    let mut path = vec![];
+    let mut blocked_by = None;
+    for p in Line::new(from, to).iter().skip(1) {
+        if !m.is_valid(p) {
+            blocked_by = Some(path.last().cloned().unwrap_or(from));
+            break;
+        }
+        path.push(p);
+        if p != to && m[p].blocks_sight() {
+            blocked_by = Some(p);
+            break;
+        }
+    }
+    LineOfFire { path, blocked_by }
+}
+
+fn first_blocker<M, C>(m: &M, from: Pos, to: Pos) -> Option<Pos>
+where
+    C: CellObject,
+    M: Index<Pos, Output = C> + HasDim,
+{
+    Line::new(from, to)
+        .iter()
+        .filter(|&p| p != from && p != to)
+        .find(|&p| m[p].blocks_sight())
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+    use proptest::{prop_assert, prop_assert_eq, proptest, proptest_helper};
+
+    use crate::common::fov::{calculate_fov_with, FovAlgorithm};
+    use crate::data::structures::matrix::Matrix;
+    use crate::data::structures::pos::PosCollection;
+
+    use super::*;
+
+    fn level_with_walls() -> BoxedStrategy<(LevelInfo, Pos, Pos)> {
+        (2..15 as DimIndex, 2..15 as DimIndex)
+            .prop_flat_map(|(width, height)| {
+                let size = width as usize * height as usize;
+                (
+                    proptest::collection::vec(proptest::bool::weighted(0.3), size),
+                    (0..width, 0..height),
+                    (0..width, 0..height),
+                )
+                    .prop_map(move |(walls, (x1, y1), (x2, y2))| {
+                        let m = Matrix::tabulate(Dim { width, height }, |p| {
+                            if walls[p.x as usize + p.y as usize * width as usize] {
+                                TileId::WALL
+                            } else {
+                                TileId::GROUND
+                            }
+                        });
+                        (
+                            LevelInfo::from_matrix(m),
+                            Pos { x: x1, y: y1 },
+                            Pos { x: x2, y: y2 },
+                        )
+                    })
+            })
+            .boxed()
+    }
+
+    #[test]
+    fn wall_stops_the_shot() {
+        let mut level = LevelInfo::from_matrix(Matrix::tabulate(
+            Dim {
+                width: 6,
+                height: 1,
+            },
+            |_| TileId::GROUND,
+        ));
+        let from = Pos { x: 0, y: 0 };
+        let to = Pos { x: 5, y: 0 };
+        let clear = line_of_fire(&level, from, to);
+        assert!(clear.is_clear());
+        assert_eq!(clear.path.len(), 5);
+
+        let wall = Pos { x: 3, y: 0 };
+        level.set(wall, TileId::WALL);
+        assert!(!has_line_of_sight(&level, from, to));
+        assert!(has_line_of_sight(&level, from, wall));
+        let shot = line_of_fire(&level, from, to);
+        assert_eq!(shot.blocked_by, Some(wall));
+        assert_eq!(
+            shot.path,
+            vec![Pos { x: 1, y: 0 }, Pos { x: 2, y: 0 }, wall]
+        );
+    }
+
+    #[test]
+    fn map_edge_stops_the_shot() {
+        let level = LevelInfo::from_matrix(Matrix::tabulate(
+            Dim {
+                width: 3,
+                height: 1,
+            },
+            |_| TileId::GROUND,
+        ));
+        let edge = Pos { x: 2, y: 0 };
+        let shot = line_of_fire(&level, Pos { x: 0, y: 0 }, Pos { x: 6, y: 0 });
+        assert_eq!(shot.path, vec![Pos { x: 1, y: 0 }, edge]);
+        assert_eq!(shot.blocked_by, Some(edge));
+
+        let outside = Pos { x: 5, y: 0 };
+        let shot = line_of_fire(&level, outside, Pos { x: 0, y: 0 });
+        assert!(shot.path.is_empty());
+        assert_eq!(shot.blocked_by, Some(outside));
+    }
+
+    proptest! {
+        #[test]
+        fn same_as_raycast_fov((level, from, _) in level_with_walls()) {
+            let fov = calculate_fov_with(&level, from, 30, FovAlgorithm::Raycast);
+            for to in level.iter_pos() {
+                prop_assert_eq!(fov[to], has_line_of_sight(&level, from, to), "{:?}", to);
+            }
+        }
+
+        #[test]
+        fn shot_stops_at_first_blocker((level, from, to) in level_with_walls()) {
+            let shot = line_of_fire(&level, from, to);
+            let end = shot.blocked_by.unwrap_or(to);
+            prop_assert_eq!(shot.path.last().cloned().unwrap_or(from), end);
+            for &p in &shot.path[..shot.path.len().saturating_sub(1)] {
+                prop_assert!(!level[p].blocks_sight());
+            }
+            if shot.is_clear() {
+                prop_assert!(has_line_of_sight(&level, from, to));
+            }
+        }
+    }
+}
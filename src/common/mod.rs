@@ -1,4 +1,7 @@
+pub mod dijkstra;
 pub mod fov;
 pub mod gen;
+pub mod los;
+pub mod path;
 pub mod query;
 pub mod validations;
@@ -0,0 +1,257 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::Index;
+
+use crate::data::structures::matrix::Matrix;
+use crate::data::structures::*;
+use crate::data::structures::{ALL_DIRS, CARDINAL_DIRS};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    /// Positions to walk through, start excluded and goal included
+    pub steps: Vec<Pos>,
+    pub cost: Cost,
+}
+
+impl Path {
+    pub fn first_step(&self) -> Option<Pos> {
+        self.steps.first().cloned()
+    }
+}
+
+/// A* search over walkable cells. Step costs must be at least 1 to keep the heuristic admissible
+pub struct AStar<'a> {
+    diagonals: bool,
+    step_cost: Option<Box<Fn(Pos, Pos) -> Cost + 'a>>,
+    blocked: Box<Fn(Pos) -> bool + 'a>,
+}
+
+impl<'a> Default for AStar<'a> {
+    fn default() -> Self {
+        AStar {
+            diagonals: false,
+            step_cost: None,
+            blocked: Box::new(|_| false),
+        }
+    }
+}
+
+impl<'a> AStar<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_diagonals(mut self, diagonals: bool) -> Self {
+        self.diagonals = diagonals;
+        self
+    }
+
+    /// Cost of stepping between two adjacent positions, instead of `movement_cost` of the cell
+    pub fn with_step_cost<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Pos, Pos) -> Cost + 'a,
+    {
+        self.step_cost = Some(Box::new(f));
+        self
+    }
+
+    /// Extra blocking on top of walkability, e.g. occupied cells. Never asked about the goal
+    pub fn with_blocker<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Pos) -> bool + 'a,
+    {
+        self.blocked = Box::new(f);
+        self
+    }
+
+    fn heuristic(&self, from: Pos, to: Pos) -> Cost {
+        if self.diagonals {
+            from.chebyshev_distance(to).into()
+        } else {
+            from.manhattan_distance(to)
+        }
+    }
+
+    fn dirs(&self) -> &'static [Dir] {
+        if self.diagonals {
+            &ALL_DIRS
+        } else {
+            &CARDINAL_DIRS
+        }
+    }
+
+    pub fn find<M, C>(&self, m: &M, from: Pos, to: Pos) -> Option<Path>
+    where
+        C: CellObject,
+        M: Index<Pos, Output = C> + HasDim,
+    {
+        if !m.is_valid(from) || !m.is_valid(to) || !m[to].is_walkable() {
+            return None;
+        }
+        let mut cost_so_far: Matrix<Option<Cost>> = Matrix::new(m.width(), m.height());
+        let mut came_from: Matrix<Option<Pos>> = Matrix::new(m.width(), m.height());
+        let mut open = BinaryHeap::new();
+
+        cost_so_far[from] = Some(0);
+        open.push(Reverse((self.heuristic(from, to), 0, from)));
+
+        while let Some(Reverse((_, cost, current))) = open.pop() {
+            if current == to {
+                return Some(Path {
+                    steps: Self::reconstruct(&came_from, from, to),
+                    cost,
+                });
+            }
+            if cost_so_far[current].map_or(false, |known| known < cost) {
+                continue;
+            }
+            for &dir in self.dirs() {
+                let next = match m.step(current, dir) {
+                    Some(next) => next,
+                    None => continue,
+                };
+                if !m[next].is_walkable() || (next != to && (self.blocked)(next)) {
+                    continue;
+                }
+                let step_cost = match self.step_cost {
+                    Some(ref f) => f(current, next),
+                    None => m[next].movement_cost(),
+                };
+                let next_cost = cost + step_cost;
+                if cost_so_far[next].map_or(true, |known| next_cost < known) {
+                    cost_so_far[next] = Some(next_cost);
+                    came_from[next] = Some(current);
+                    let priority = next_cost + self.heuristic(next, to);
+                    open.push(Reverse((priority, next_cost, next)));
+                }
+            }
+        }
+        None
+    }
+
+    fn reconstruct(came_from: &Matrix<Option<Pos>>, from: Pos, to: Pos) -> Vec<Pos> {
+        let mut steps = vec![];
+        let mut current = to;
+        while current != from {
+            steps.push(current);
+            current = came_from[current].expect("AStar::reconstruct broken chain");
+        }
+        steps.reverse();
+        steps
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+    use proptest::{prop_assert, prop_assert_eq, proptest, proptest_helper};
+
+    use super::*;
+
+    fn open_level(width: DimIndex, height: DimIndex) -> LevelInfo {
+        LevelInfo::from_matrix(Matrix::tabulate(Dim { width, height }, |_| TileId::GROUND))
+    }
+
+    /// Small random grids, mostly open so that paths actually exist
+    fn arb_grid() -> BoxedStrategy<Matrix<bool>> {
+        (1..16 as DimIndex, 1..16 as DimIndex)
+            .prop_flat_map(|(width, height)| {
+                let dim = Dim { width, height };
+                let size = width as usize * height as usize;
+                proptest::collection::vec(proptest::bool::weighted(0.7), size).prop_map(
+                    move |cells| {
+                        Matrix::tabulate(dim, |p| {
+                            p == Pos::default()
+                                || cells[p.x as usize + p.y as usize * width as usize]
+                        })
+                    },
+                )
+            })
+            .boxed()
+    }
+
+    fn to_level(m: &Matrix<bool>) -> LevelInfo {
+        LevelInfo::from_matrix(m.map(|&open| if open { TileId::GROUND } else { TileId::WALL }))
+    }
+
+    #[test]
+    fn walks_around_walls() {
+        let mut level = open_level(3, 3);
+        level.set(Pos { x: 1, y: 0 }, TileId::WALL);
+        level.set(Pos { x: 1, y: 1 }, TileId::WALL);
+        let path = AStar::new()
+            .find(&level, Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 })
+            .unwrap();
+        assert_eq!(path.cost, 6);
+        assert_eq!(path.steps.len(), 6);
+        assert!(path.steps.iter().all(|&p| level[p].is_walkable()));
+    }
+
+    #[test]
+    fn diagonals_shorten_path() {
+        let level = open_level(5, 5);
+        let (from, to) = (Pos { x: 0, y: 0 }, Pos { x: 4, y: 4 });
+        assert_eq!(AStar::new().find(&level, from, to).unwrap().cost, 8);
+        let diagonal = AStar::new().with_diagonals(true).find(&level, from, to);
+        assert_eq!(diagonal.unwrap().cost, 4);
+    }
+
+    #[test]
+    fn blocker_is_avoided_but_not_for_goal() {
+        let level = open_level(3, 1);
+        let blocked = Pos { x: 1, y: 0 };
+        let astar = AStar::new().with_blocker(|p| p == blocked);
+        assert_eq!(
+            astar.find(&level, Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 }),
+            None
+        );
+        let path = astar.find(&level, Pos { x: 0, y: 0 }, blocked).unwrap();
+        assert_eq!(path.steps, vec![blocked]);
+    }
+
+    #[test]
+    fn step_cost_prefers_cheap_detour() {
+        let level = open_level(3, 2);
+        let swamp = Pos { x: 1, y: 0 };
+        let path = AStar::new()
+            .with_step_cost(|_, to| if to == swamp { 10 } else { 1 })
+            .find(&level, Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 })
+            .unwrap();
+        assert_eq!(path.cost, 4);
+        assert!(!path.steps.contains(&swamp));
+    }
+
+    proptest! {
+        #[test]
+        fn path_is_made_of_adjacent_walkable_steps(ref m in arb_grid(), diagonals: bool) {
+            let level = to_level(m);
+            let from = Pos::default();
+            let to = level.max_pos();
+            let astar = AStar::new().with_diagonals(diagonals);
+            if let Some(path) = astar.find(&level, from, to) {
+                prop_assert_eq!(path.cost as usize, path.steps.len());
+                prop_assert_eq!(path.steps.last().unwrap_or(&from), &to);
+                let mut previous = from;
+                for &p in &path.steps {
+                    prop_assert!(level[p].is_walkable());
+                    let expected = if diagonals {
+                        previous.chebyshev_distance(p) as u32
+                    } else {
+                        previous.manhattan_distance(p)
+                    };
+                    prop_assert_eq!(expected, 1);
+                    previous = p;
+                }
+            }
+        }
+
+        #[test]
+        fn finds_path_iff_flood_fill_reaches(ref m in arb_grid()) {
+            let level = to_level(m);
+            let from = Pos::default();
+            let to = level.max_pos();
+            let reachable = m.flood_fill(from, |&open| open)[to];
+            prop_assert_eq!(AStar::new().find(&level, from, to).is_some(), reachable);
+        }
+    }
+}
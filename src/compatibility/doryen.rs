@@ -1,6 +1,7 @@
 use crate::data::structures::*;
 use crate::systems::render::Renderable;
 use crate::systems::render::Renderer;
+use crate::systems::render::LOG_LINES;
 use crate::ui::Game;
 use doryen_rs::*;
 use specs::prelude::*;
@@ -9,16 +10,24 @@ use specs::prelude::*;
 pub struct GameContext<'a, 'b> {
     renderer: DoryenRenderer,
     game: Game<'a, 'b>,
+    needs_tick: bool,
 }
 
 impl<'a, 'b> GameContext<'a, 'b> {
     pub fn from_specs(mut w: World) -> Self {
         let console: doryen_rs::Console = w.exec(|level_info: Read<LevelInfo>| {
-            doryen_rs::Console::new(level_info.width().into(), level_info.height().into())
+            doryen_rs::Console::new(
+                level_info.width().into(),
+                (level_info.height() + LOG_LINES).into(),
+            )
         });
         let game = Game::new(w);
         let renderer = DoryenRenderer { console };
-        GameContext { game, renderer }
+        GameContext {
+            game,
+            renderer,
+            needs_tick: true,
+        }
     }
 }
 struct DoryenRenderer {
@@ -32,21 +41,37 @@ impl DoryenRenderer {
 }
 
 impl Renderer for DoryenRenderer {
-    fn render<T>(&mut self, pos: Pos, renderable: &T, in_fov: bool)
+    fn render<T>(&mut self, pos: Pos, renderable: &T, light: Option<Light>)
     where
         T: Renderable,
     {
-        let color = if in_fov {
-            renderable.color()
-        } else {
-            renderable.color() * 0.5
+        let shade = |color| match light {
+            Some(light) => light.tint(color),
+            None => color * 0.5,
         };
+        if let Some(bg) = renderable.background() {
+            let bg = shade(bg);
+            self.console
+                .back(pos.x.into(), pos.y.into(), (bg.r, bg.g, bg.b, 255));
+        }
+        let color = shade(renderable.color());
         self.console
             .fore(pos.x.into(), pos.y.into(), (color.r, color.g, color.b, 255));
         self.console
             .ascii(pos.x.into(), pos.y.into(), renderable.display_char() as u16);
     }
 
+    fn print(&mut self, pos: Pos, text: &str) {
+        self.console.print(
+            pos.x.into(),
+            pos.y.into(),
+            text,
+            TextAlign::Left,
+            Some((255, 255, 255, 255)),
+            None,
+        );
+    }
+
     fn clear(&mut self) {
         self.console.clear(None, None, None);
     }
@@ -57,12 +82,20 @@ impl<'a, 'b> Engine for GameContext<'a, 'b> {
         let input = api.input();
         for (key, command) in self.game.key_mapper.commands() {
             if input.key_pressed(key) {
-                self.game
+                if self
+                    .game
                     .game_command_handler
-                    .exec(command, &mut self.game.world);
+                    .exec(command, &mut self.game.world)
+                {
+                    self.needs_tick = true;
+                }
             }
         }
-        self.game.update();
+        // Monsters act once per player command, not once per frame
+        if self.needs_tick || self.game.is_busy() {
+            self.game.update();
+            self.needs_tick = false;
+        }
     }
     fn render(&mut self, api: &mut DoryenApi) {
         {
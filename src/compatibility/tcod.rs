@@ -1,18 +1,22 @@
 use tcod::BackgroundFlag;
+use tcod::TextAlignment;
 
-use crate::data::structures::Pos;
+use crate::data::structures::{Light, Pos};
 use crate::systems::render::{Renderable, Renderer};
 
 impl<'a, C: ::tcod::Console> Renderer for C {
-    fn render<T>(&mut self, pos: Pos, r: &T, in_fov: bool)
+    fn render<T>(&mut self, pos: Pos, r: &T, light: Option<Light>)
     where
         T: Renderable,
     {
-        let mut color = r.color();
-        if !in_fov {
-            color = color * 0.5;
+        let shade = |color| match light {
+            Some(light) => light.tint(color),
+            None => color * 0.5,
+        };
+        if let Some(bg) = r.background() {
+            self.set_char_background(pos.x.into(), pos.y.into(), shade(bg), BackgroundFlag::Set);
         }
-        self.set_default_foreground(color);
+        self.set_default_foreground(shade(r.color()));
         self.put_char(
             pos.x.into(),
             pos.y.into(),
@@ -21,6 +25,17 @@ impl<'a, C: ::tcod::Console> Renderer for C {
         );
     }
 
+    fn print(&mut self, pos: Pos, text: &str) {
+        self.set_default_foreground(::tcod::colors::WHITE);
+        self.print_ex(
+            pos.x.into(),
+            pos.y.into(),
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            text,
+        );
+    }
+
     fn clear(&mut self) {
         self.clear();
     }
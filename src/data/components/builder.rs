@@ -1,8 +1,8 @@
 use specs::prelude::*;
 
 use crate::data::components::*;
-use crate::data::structures::world_data::MonsterTemplate;
-use crate::data::structures::Pos;
+use crate::data::structures::world_data::{ItemTemplate, MonsterTemplate};
+use crate::data::structures::{DimIndex, Pos};
 use crate::systems::render::Color;
 use crate::systems::render::Renderable;
 
@@ -13,7 +13,11 @@ where
     fn with_actor_components(self, display_char: char, color: Color, pos: Pos) -> Self;
     fn is_player(self) -> Self;
     fn with_ai(self) -> Self;
+    fn with_item(self) -> Self;
     fn with_fighter(self, f: IsFighter) -> Self;
+    fn with_perception(self, sight_radius: DimIndex) -> Self;
+    fn with_name(self, name: &'static str) -> Self;
+    fn is_lamp(self, pos: Pos, radius: DimIndex) -> Self;
 
     fn is_monster(self, mt: &MonsterTemplate, pos: Pos) -> Self {
         let display_char = mt.display_char();
@@ -21,6 +25,14 @@ where
         self.with_actor_components(display_char, color, pos)
             .with_fighter(mt.fight_skills().clone())
             .with_ai()
+            .with_perception(mt.sight_radius())
+            .with_name(mt.name())
+    }
+
+    fn is_item(self, it: &ItemTemplate, pos: Pos) -> Self {
+        self.with_actor_components(it.display_char(), it.color(), pos)
+            .with_item()
+            .with_name(it.name)
     }
 }
 
@@ -42,7 +54,23 @@ impl<'a> RichEntityBuilder for EntityBuilder<'a> {
         self.with(HasBrain {})
     }
 
+    fn with_item(self) -> Self {
+        self.with(IsItem)
+    }
+
     fn with_fighter(self, f: IsFighter) -> Self {
         self.with(f)
     }
+
+    fn with_perception(self, sight_radius: DimIndex) -> Self {
+        self.with(HasPerception::new(sight_radius))
+    }
+
+    fn with_name(self, name: &'static str) -> Self {
+        self.with(HasName(name))
+    }
+
+    fn is_lamp(self, pos: Pos, radius: DimIndex) -> Self {
+        self.with(HasPos(pos)).with(EmitsLight::lamp(radius))
+    }
 }
@@ -1,7 +1,8 @@
 use specs::prelude::*;
 use specs_derive::*;
 
-use crate::data::structures::matrix::Matrix;
+use crate::common::fov::{FovAlgorithm, FovWindow};
+use crate::data::structures::bit_matrix::BitMatrix;
 use crate::systems::render::Color;
 
 use super::structures::*;
@@ -11,24 +12,6 @@ use std::cmp::max;
 
 mod builder;
 
-impl<'a> std::ops::AddAssign<&'a Dir> for &'a mut Pos {
-    fn add_assign(&mut self, other: &'a Dir) {
-        let o = **self + *other;
-        self.x = o.x;
-        self.y = o.y;
-    }
-}
-
-impl<'a> std::ops::Add<Dir> for Pos {
-    type Output = Pos;
-
-    fn add(self, other: Dir) -> Pos {
-        let x: DimIndex = other.ew.apply(self.x);
-        let y: DimIndex = other.ns.apply(self.y);
-        Pos { x, y }
-    }
-}
-
 #[derive(Component, Debug)]
 pub struct IsVisible {
     pub display_char: char,
@@ -38,6 +21,10 @@ pub struct IsVisible {
 #[derive(Component, Debug, Default)]
 pub struct IsPlayer;
 
+/// Lies on the floor instead of standing there, so it doesn't get in anyone's way
+#[derive(Component, Debug, Default)]
+pub struct IsItem;
+
 #[derive(Component, Debug, PartialEq)]
 pub struct PlansExecuting(pub ActorCommand);
 impl PlansExecuting {
@@ -46,20 +33,41 @@ impl PlansExecuting {
     }
 }
 
-#[derive(Component, PartialEq, Eq, Hash, Debug)]
+#[derive(PartialEq, Eq, Hash, Debug)]
 pub struct HasPos(pub Pos);
 
+/// Flagged so `SpatialIndex` follows only the entities that moved
+impl Component for HasPos {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
+#[derive(Component, Debug, PartialEq)]
+pub struct HasActivity(pub Activity);
+
+/// Entity of a level the player is not on. It keeps its position there instead of `HasPos`,
+/// so systems leave it alone until the player comes back
+#[derive(Component, Debug, PartialEq)]
+pub struct Dormant {
+    pub depth: Depth,
+    pub pos: Pos,
+}
+
 #[derive(Component)]
 pub struct HasVision {
     pub radius: DimIndex,
-    fov: Option<Matrix<bool>>,
-    memory: Option<Matrix<bool>>,
+    pub algorithm: FovAlgorithm,
+    fov: Option<FovWindow>,
+    memory: Option<BitMatrix>,
+    /// Level `memory` is about
+    memory_of: Option<LevelId>,
 }
 
 type Attr = i32;
 #[derive(Component, Default, Debug)]
 pub struct HasEffectStack {
     pub attack_power: Attr,
+    /// Damage from the terrain, defense doesn't help against it
+    pub terrain_damage: Attr,
 }
 
 impl HasEffectStack {
@@ -72,38 +80,141 @@ impl HasVision {
     pub fn new(radius: DimIndex) -> Self {
         HasVision {
             radius,
+            algorithm: FovAlgorithm::default(),
             fov: None,
             memory: None,
+            memory_of: None,
         }
     }
+    pub fn with_algorithm(self, algorithm: FovAlgorithm) -> Self {
+        HasVision { algorithm, ..self }
+    }
     pub fn expire_fov(&mut self) {
         self.fov = None;
     }
-    pub fn fov(&self) -> Option<&Matrix<bool>> {
+    pub fn fov(&self) -> Option<&FovWindow> {
         self.fov.as_ref()
     }
 
-    pub fn set_fov(&mut self, m: Matrix<bool>) {
-        if self.memory.is_none() {
-            self.memory = Some(Matrix::new(m.width(), m.height()));
+    /// Visible tiles are remembered, memory grows to the size of the whole level
+    pub fn set_fov(&mut self, fov: FovWindow) {
+        let dim = fov.dim();
+        let memory = self.memory.get_or_insert_with(|| BitMatrix::with_dim(dim));
+        if let Err(e) = fov.merge_into(memory) {
+            log::warn!("Forgetting memory of another level: {:?}", e);
+            *memory = BitMatrix::with_dim(dim);
+            fov.merge_into(memory).expect("HasVision::set_fov");
         }
-        let mem = self.memory.as_mut().unwrap();
-        for (p, v) in m.iter() {
-            if *v {
-                mem[p] = true;
-            }
+        self.fov = Some(fov);
+    }
+
+    /// Drops what was seen on other levels, unless memory of `level` was restored already
+    pub fn enter_level(&mut self, level: LevelId) {
+        self.fov = None;
+        if self.memory_of != Some(level) {
+            self.memory = None;
+            self.memory_of = Some(level);
         }
-        self.fov = Some(m);
     }
 
-    pub fn memory(&self) -> Option<&Matrix<bool>> {
+    /// Memory of the level being left, to be restored when coming back
+    pub fn take_memory(&mut self) -> Option<BitMatrix> {
+        self.fov = None;
+        self.memory_of = None;
+        self.memory.take()
+    }
+
+    pub fn restore_memory(&mut self, level: LevelId, memory: BitMatrix) {
+        self.fov = None;
+        self.memory = Some(memory);
+        self.memory_of = Some(level);
+    }
+
+    pub fn memory(&self) -> Option<&BitMatrix> {
         self.memory.as_ref()
     }
 }
 
+/// Lights up tiles around it. Intensity goes down from 1 at the source to 0 just past `radius`,
+/// `falloff` is the power of that curve: 0 lights evenly, 1 fades linearly
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct EmitsLight {
+    pub radius: DimIndex,
+    pub color: Color,
+    pub falloff: f32,
+}
+
+impl EmitsLight {
+    pub fn torch() -> Self {
+        EmitsLight {
+            radius: 6,
+            color: Color {
+                r: 255,
+                g: 200,
+                b: 130,
+            },
+            falloff: 1.0,
+        }
+    }
+
+    /// Steady light filling a room
+    pub fn lamp(radius: DimIndex) -> Self {
+        EmitsLight {
+            radius,
+            color: Color {
+                r: 255,
+                g: 250,
+                b: 230,
+            },
+            falloff: 0.3,
+        }
+    }
+
+    pub fn intensity_at(&self, distance: f64) -> f32 {
+        let reach = f64::from(self.radius) + 1.0;
+        (1.0 - distance / reach)
+            .max(0.0)
+            .powf(f64::from(self.falloff)) as f32
+    }
+}
+
 #[derive(Component)]
 pub struct HasBrain {}
 
+/// What the entity is, monsters are named after their race
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct HasName(pub &'static str);
+
+/// What a monster knows about where the player is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Awareness {
+    Unaware,
+    /// Player is in sight right now
+    Sees(Pos),
+    /// Player went out of sight, last seen at that position
+    Remembers(Pos),
+}
+
+/// Notices the player in line of sight within `sight_radius`, as long as the player stands in light
+#[derive(Component, Debug, Clone)]
+pub struct HasPerception {
+    pub sight_radius: DimIndex,
+    pub awareness: Awareness,
+}
+
+impl HasPerception {
+    pub fn new(sight_radius: DimIndex) -> Self {
+        HasPerception {
+            sight_radius,
+            awareness: Awareness::Unaware,
+        }
+    }
+
+    pub fn is_aware(&self) -> bool {
+        self.awareness != Awareness::Unaware
+    }
+}
+
 #[derive(Component, Clone, Debug)]
 pub struct IsFighter {
     pub max_hp: Attr,
@@ -127,7 +238,17 @@ impl IsFighter {
         self.current_hp -= damage;
     }
 
+    /// Damage which goes past defense
+    pub fn inflict_terrain_damage(&mut self, damage: Attr) {
+        self.current_hp -= max(0, damage);
+    }
+
     pub fn is_dead(&self) -> bool {
         self.current_hp < 0
     }
+
+    /// Hurt enough to prefer running away
+    pub fn is_badly_hurt(&self) -> bool {
+        self.current_hp * 4 <= self.max_hp
+    }
 }
@@ -0,0 +1,358 @@
+use std::ops::Index;
+
+use crate::data::structures::matrix::*;
+use crate::data::structures::rect::{Rect, RectIter};
+
+type Block = u64;
+const BLOCK_BITS: usize = 64;
+
+static TRUE: bool = true;
+static FALSE: bool = false;
+
+/// Boolean grid storing one bit per tile, used for FOV and explored-memory layers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BitMatrix {
+    width: DimIndex,
+    height: DimIndex,
+    data: Vec<Block>,
+}
+
+impl BitMatrix {
+    pub fn new(width: DimIndex, height: DimIndex) -> Self {
+        let bits = width as usize * height as usize;
+        BitMatrix {
+            width,
+            height,
+            data: vec![0; (bits + BLOCK_BITS - 1) / BLOCK_BITS],
+        }
+    }
+
+    pub fn with_dim(dim: Dim) -> Self {
+        Self::new(dim.width, dim.height)
+    }
+
+    pub fn tabulate<F>(dim: Dim, mut f: F) -> Self
+    where
+        F: FnMut(Pos) -> bool,
+    {
+        let mut m = Self::with_dim(dim);
+        for p in m.iter_pos() {
+            if f(p) {
+                m.set(p, true);
+            }
+        }
+        m
+    }
+
+    fn to_index(&self, pos: Pos) -> usize {
+        assert!(self.is_valid(pos));
+        pos.x as usize + pos.y as usize * self.width as usize
+    }
+
+    fn to_pos(&self, i: usize) -> Pos {
+        Pos {
+            x: (i % self.width as usize) as DimIndex,
+            y: (i / self.width as usize) as DimIndex,
+        }
+    }
+
+    fn bit(i: usize) -> (usize, Block) {
+        (i / BLOCK_BITS, 1 << (i % BLOCK_BITS))
+    }
+
+    pub fn get(&self, pos: Pos) -> bool {
+        let (block, mask) = Self::bit(self.to_index(pos));
+        self.data[block] & mask != 0
+    }
+
+    pub fn set(&mut self, pos: Pos, value: bool) {
+        let (block, mask) = Self::bit(self.to_index(pos));
+        if value {
+            self.data[block] |= mask;
+        } else {
+            self.data[block] &= !mask;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for b in &mut self.data {
+            *b = 0;
+        }
+    }
+
+    pub fn dim(&self) -> Dim {
+        Dim {
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.data.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    pub fn union_with(&mut self, other: &BitMatrix) {
+        self.assert_same_dim(other);
+        for (b, o) in self.data.iter_mut().zip(other.data.iter()) {
+            *b |= *o;
+        }
+    }
+
+    pub fn intersect_with(&mut self, other: &BitMatrix) {
+        self.assert_same_dim(other);
+        for (b, o) in self.data.iter_mut().zip(other.data.iter()) {
+            *b &= *o;
+        }
+    }
+
+    /// Sets bits which are set in `other` placed with its top left corner at `at`. Works on whole
+    /// blocks of each row instead of bit by bit
+    pub fn union_at(&mut self, other: &BitMatrix, at: Pos) {
+        assert!(at.x as usize + other.width as usize <= self.width as usize);
+        assert!(at.y as usize + other.height as usize <= self.height as usize);
+        let width = other.width as usize;
+        for y in 0..other.height as usize {
+            let from = y * width;
+            let to = at.x as usize + (at.y as usize + y) * self.width as usize;
+            let mut done = 0;
+            while done < width {
+                let len = usize::min(BLOCK_BITS, width - done);
+                let bits = other.read_bits(from + done, len);
+                self.or_bits(to + done, len, bits);
+                done += len;
+            }
+        }
+    }
+
+    /// `len` bits starting from bit `start`, `len` is at most a block
+    fn read_bits(&self, start: usize, len: usize) -> Block {
+        let (block, offset) = (start / BLOCK_BITS, start % BLOCK_BITS);
+        let mut bits = self.data[block] >> offset;
+        if offset + len > BLOCK_BITS {
+            bits |= self.data[block + 1] << (BLOCK_BITS - offset);
+        }
+        if len < BLOCK_BITS {
+            bits & ((1 << len) - 1)
+        } else {
+            bits
+        }
+    }
+
+    /// Sets bits of `bits`, which are `len` long, starting from bit `start`
+    fn or_bits(&mut self, start: usize, len: usize, bits: Block) {
+        let (block, offset) = (start / BLOCK_BITS, start % BLOCK_BITS);
+        self.data[block] |= bits << offset;
+        if offset + len > BLOCK_BITS {
+            self.data[block + 1] |= bits >> (BLOCK_BITS - offset);
+        }
+    }
+
+    fn assert_same_dim(&self, other: &BitMatrix) {
+        assert_eq!(self.width, other.width);
+        assert_eq!(self.height, other.height);
+    }
+
+    pub fn iter(&self) -> BitMatrixIter {
+        BitMatrixIter {
+            matrix: self,
+            next: 0,
+        }
+    }
+
+    /// Positions of all set bits, skipping empty blocks
+    pub fn ones(&self) -> impl Iterator<Item = Pos> + '_ {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b != 0)
+            .flat_map(move |(i, b)| {
+                (0..BLOCK_BITS)
+                    .filter(move |bit| b & (1 << bit) != 0)
+                    .map(move |bit| self.to_pos(i * BLOCK_BITS + bit))
+            })
+    }
+}
+
+impl<'a> From<&'a Matrix<bool>> for BitMatrix {
+    fn from(m: &'a Matrix<bool>) -> Self {
+        BitMatrix::tabulate(m.dim(), |p| m[p])
+    }
+}
+
+impl Index<Pos> for BitMatrix {
+    type Output = bool;
+
+    fn index(&self, pos: Pos) -> &bool {
+        if self.get(pos) {
+            &TRUE
+        } else {
+            &FALSE
+        }
+    }
+}
+
+impl HasDim for BitMatrix {
+    fn width(&self) -> DimIndex {
+        self.width
+    }
+
+    fn height(&self) -> DimIndex {
+        self.height
+    }
+}
+
+impl PosCollection for BitMatrix {
+    type Iter = RectIter;
+
+    fn iter_pos(&self) -> Self::Iter {
+        Rect::with_dim(Pos::default(), self.dim()).iter()
+    }
+}
+
+#[derive(Debug)]
+pub struct BitMatrixIter<'a> {
+    matrix: &'a BitMatrix,
+    next: usize,
+}
+
+impl<'a> Iterator for BitMatrixIter<'a> {
+    type Item = (Pos, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.matrix.width as usize * self.matrix.height as usize {
+            return None;
+        }
+        let pos = self.matrix.to_pos(self.next);
+        self.next += 1;
+        Some((pos, self.matrix.get(pos)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use proptest::{prop_assert, prop_assert_eq, proptest, proptest_helper};
+
+    use super::*;
+    use crate::data::structures::dim::test::nonzero_dim;
+    use crate::data::structures::pos::test::SMALL_DIM;
+
+    fn bool_matrix() -> BoxedStrategy<Matrix<bool>> {
+        nonzero_dim(SMALL_DIM)
+            .prop_flat_map(|dim| {
+                let size = dim.width as usize * dim.height as usize;
+                (Just(dim), vec(any::<bool>(), size))
+            })
+            .prop_map(|(dim, bits)| {
+                let width = dim.width as usize;
+                Matrix::tabulate(dim, |p| bits[p.x as usize + p.y as usize * width])
+            })
+            .boxed()
+    }
+
+    fn bool_matrix_pair() -> BoxedStrategy<(Matrix<bool>, Matrix<bool>)> {
+        bool_matrix()
+            .prop_flat_map(|m1| {
+                let size = m1.width() as usize * m1.height() as usize;
+                (Just(m1), vec(any::<bool>(), size))
+            })
+            .prop_map(|(m1, bits)| {
+                let width = m1.width() as usize;
+                let m2 = Matrix::tabulate(m1.dim(), |p| bits[p.x as usize + p.y as usize * width]);
+                (m1, m2)
+            })
+            .boxed()
+    }
+
+    /// Matrix wider than a block and a smaller one which fits into it at some position
+    fn placed_matrices() -> BoxedStrategy<(Matrix<bool>, Matrix<bool>, Pos)> {
+        (1..150 as DimIndex, 1..10 as DimIndex)
+            .prop_flat_map(|(width, height)| {
+                let size = width as usize * height as usize;
+                (
+                    Just(Dim { width, height }),
+                    vec(any::<bool>(), size),
+                    1..=width,
+                    1..=height,
+                )
+            })
+            .prop_flat_map(|(dim, bits, width, height)| {
+                let size = width as usize * height as usize;
+                (
+                    Just(Matrix::tabulate(dim, |p| {
+                        bits[p.x as usize + p.y as usize * dim.width as usize]
+                    })),
+                    vec(any::<bool>(), size).prop_map(move |bits| {
+                        Matrix::tabulate(Dim { width, height }, |p| {
+                            bits[p.x as usize + p.y as usize * width as usize]
+                        })
+                    }),
+                    0..=dim.width - width,
+                    0..=dim.height - height,
+                )
+            })
+            .prop_map(|(big, small, x, y)| (big, small, Pos { x, y }))
+            .boxed()
+    }
+
+    proptest! {
+        #[test]
+        fn union_at_sets_bits_of_placed_matrix((big, small, at) in placed_matrices()) {
+            let mut union = BitMatrix::from(&big);
+            union.union_at(&BitMatrix::from(&small), at);
+            for p in big.iter_pos() {
+                let placed = p.x >= at.x && p.y >= at.y && small.is_valid(Pos {
+                    x: p.x - at.x,
+                    y: p.y - at.y,
+                }) && small[Pos {
+                    x: p.x - at.x,
+                    y: p.y - at.y,
+                }];
+                prop_assert_eq!(big[p] || placed, union[p], "{:?}", p);
+            }
+        }
+
+        #[test]
+        fn same_as_matrix(m in bool_matrix()) {
+            let bits = BitMatrix::from(&m);
+            for (p, v) in m.iter() {
+                prop_assert_eq!(*v, bits[p]);
+            }
+            prop_assert_eq!(m.count_where(|&v| v), bits.count_ones());
+            prop_assert_eq!(m.count_where(|&v| v), bits.ones().count());
+            for p in bits.ones() {
+                prop_assert!(m[p]);
+            }
+            for ((p1, v1), (p2, v2)) in m.iter().zip(bits.iter()) {
+                prop_assert_eq!(p1, p2);
+                prop_assert_eq!(*v1, v2);
+            }
+        }
+
+        #[test]
+        fn set_and_reset(m in bool_matrix()) {
+            let mut bits = BitMatrix::with_dim(m.dim());
+            for (p, v) in m.iter() {
+                bits.set(p, *v);
+            }
+            prop_assert_eq!(&BitMatrix::from(&m), &bits);
+            for p in m.iter_pos() {
+                bits.set(p, false);
+            }
+            prop_assert_eq!(0, bits.count_ones());
+        }
+
+        #[test]
+        fn union_and_intersection((m1, m2) in bool_matrix_pair()) {
+            let mut union = BitMatrix::from(&m1);
+            union.union_with(&BitMatrix::from(&m2));
+            let mut intersection = BitMatrix::from(&m1);
+            intersection.intersect_with(&BitMatrix::from(&m2));
+            for p in m1.iter_pos() {
+                prop_assert_eq!(m1[p] || m2[p], union[p]);
+                prop_assert_eq!(m1[p] && m2[p], intersection[p]);
+            }
+        }
+    }
+}
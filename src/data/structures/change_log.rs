@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+
+use crate::data::structures::rect::Rect;
+
+/// Grows with every recorded change
+pub type Version = u64;
+
+const MAX_CHANGES: usize = 64;
+
+/// Areas changed lately, so that whoever saw an older version can update only those.
+/// Only the latest changes are kept
+#[derive(Debug, Clone, Default)]
+pub struct ChangeLog {
+    version: Version,
+    /// Latest changes, each with the version it produced
+    changes: VecDeque<(Version, Rect)>,
+    /// Newest version no longer covered by `changes`
+    forgotten: Version,
+}
+
+impl ChangeLog {
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Areas changed after `since`, or `None` when the log doesn't reach that far back anymore
+    /// and everything has to be considered changed
+    pub fn since(&self, since: Version) -> Option<impl Iterator<Item = Rect> + '_> {
+        if since < self.forgotten || since > self.version {
+            return None;
+        }
+        Some(
+            self.changes
+                .iter()
+                .filter(move |(v, _)| *v > since)
+                .map(|(_, r)| *r),
+        )
+    }
+
+    pub fn record(&mut self, area: Rect) {
+        self.version += 1;
+        if self.changes.len() == MAX_CHANGES {
+            if let Some((v, _)) = self.changes.pop_front() {
+                self.forgotten = v;
+            }
+        }
+        self.changes.push_back((self.version, area));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::structures::Pos;
+
+    #[test]
+    fn old_changes_are_forgotten() {
+        let mut log = ChangeLog::default();
+        let p = Pos { x: 0, y: 0 };
+        for _ in 0..=MAX_CHANGES {
+            log.record(Rect::inclusive(p, p));
+        }
+        assert!(log.since(0).is_none());
+        assert_eq!(log.since(1).unwrap().count(), MAX_CHANGES);
+        assert!(log.since(log.version() + 1).is_none());
+    }
+}
@@ -1,7 +1,8 @@
 use crate::data::structures::DimIndex;
+use crate::data::structures::Dir;
 use crate::data::structures::Pos;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Dim {
     pub width: DimIndex,
     pub height: DimIndex,
@@ -11,6 +12,13 @@ pub trait HasDim {
     fn width(&self) -> DimIndex;
     fn height(&self) -> DimIndex;
 
+    fn dim(&self) -> Dim {
+        Dim {
+            width: self.width(),
+            height: self.height(),
+        }
+    }
+
     fn max_pos(&self) -> Pos {
         Pos {
             x: self.width() - 1,
@@ -21,6 +29,10 @@ pub trait HasDim {
     fn is_valid(&self, p: Pos) -> bool {
         p.x < self.width() && p.y < self.height()
     }
+
+    fn step(&self, p: Pos, dir: Dir) -> Option<Pos> {
+        (p + dir).filter(|&p| self.is_valid(p))
+    }
 }
 
 impl HasDim for Dim {
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use crate::data::structures::bit_matrix::BitMatrix;
+use crate::data::structures::LevelInfo;
+
+/// How deep a level is, the first one is at depth 1
+pub type Depth = usize;
+
+/// Level the player left, kept as it was to be restored on return
+#[derive(Debug)]
+pub struct Floor {
+    pub level: LevelInfo,
+    /// What the player remembers of the level
+    pub memory: Option<BitMatrix>,
+}
+
+/// Levels of the game, linked with stairs. The one being played is the `LevelInfo` resource,
+/// the others wait here. Entities of other levels are kept in the world as `Dormant`
+#[derive(Debug)]
+pub struct Dungeon {
+    depth: Depth,
+    floors: HashMap<Depth, Floor>,
+}
+
+impl Default for Dungeon {
+    fn default() -> Self {
+        Dungeon {
+            depth: 1,
+            floors: HashMap::new(),
+        }
+    }
+}
+
+impl Dungeon {
+    /// Depth of the level being played
+    pub fn depth(&self) -> Depth {
+        self.depth
+    }
+
+    /// Level at `depth` waiting for the player to return
+    pub fn floor(&self, depth: Depth) -> Option<&Floor> {
+        self.floors.get(&depth)
+    }
+
+    /// Puts away the level being left and moves to `depth`, returning it if it was visited before
+    pub fn switch(&mut self, left: Floor, depth: Depth) -> Option<Floor> {
+        self.floors.insert(self.depth, left);
+        self.depth = depth;
+        self.floors.remove(&depth)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn floor() -> Floor {
+        Floor {
+            level: LevelInfo::new(3, 3),
+            memory: None,
+        }
+    }
+
+    #[test]
+    fn left_floors_are_restored() {
+        let mut dungeon = Dungeon::default();
+        let first = floor();
+        let id = first.level.id();
+        assert!(dungeon.switch(first, 2).is_none());
+        assert_eq!(dungeon.depth(), 2);
+        assert_eq!(dungeon.floor(1).map(|f| f.level.id()), Some(id));
+        assert!(dungeon.floor(3).is_none());
+        let back = dungeon.switch(floor(), 1).unwrap();
+        assert_eq!(back.level.id(), id);
+        assert_eq!(dungeon.depth(), 1);
+        assert!(dungeon.floor(1).is_none());
+        assert!(dungeon.floor(2).is_some());
+    }
+}
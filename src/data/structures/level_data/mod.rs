@@ -1,6 +1,11 @@
-use std::ops::{Index, IndexMut};
+use std::ops::Index;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
+use crate::data::structures::change_log::{ChangeLog, Version};
 use crate::data::structures::matrix::*;
+use crate::data::structures::rect::Rect;
+use crate::data::structures::tiles::{TileDef, TileId, TileRegistry};
 
 pub mod corridor;
 pub mod room;
@@ -8,62 +13,59 @@ pub mod room;
 pub use self::corridor::*;
 pub use self::room::*;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum TileType {
-    Wall,
-    Ground,
-    RoomWall,
-}
+/// Price of stepping onto a cell, paths look for the cheapest way
+pub type Cost = u32;
 
 pub trait CellObject {
     fn is_walkable(&self) -> bool;
     fn blocks_sight(&self) -> bool;
-}
 
-impl CellObject for TileType {
-    fn is_walkable(&self) -> bool {
-        match self {
-            TileType::Wall => false,
-            TileType::RoomWall => false,
-            _ => true,
-        }
+    /// How expensive it is to step onto the cell when looking for a path
+    fn movement_cost(&self) -> Cost {
+        1
     }
 
-    fn blocks_sight(&self) -> bool {
-        match self {
-            TileType::Wall => true,
-            TileType::RoomWall => true,
-            _ => false,
-        }
+    /// Damage dealt to whoever steps onto the cell
+    fn damage_on_enter(&self) -> i32 {
+        0
     }
-}
 
-impl Default for TileType {
-    fn default() -> Self {
-        TileType::Wall
+    /// Walking into the cell opens it instead of moving
+    fn is_openable(&self) -> bool {
+        false
     }
 }
 
-#[derive(Debug, Default, Clone)]
+/// Tells levels apart, every new level gets its own
+pub type LevelId = usize;
+/// Grows with every change of the terrain
+pub type LevelVersion = Version;
+
+static NEXT_LEVEL_ID: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Debug, Clone)]
 pub struct LevelInfo {
-    data: Matrix<TileType>,
+    data: Matrix<TileId>,
+    tiles: Arc<TileRegistry>,
+    id: LevelId,
+    changes: ChangeLog,
 }
 
-impl<'a> Index<Pos> for LevelInfo {
-    type Output = TileType;
-    fn index(&self, i: Pos) -> &TileType {
-        &self.data[i]
+impl Default for LevelInfo {
+    fn default() -> Self {
+        Self::from_matrix(Matrix::default())
     }
 }
 
-impl<'a> IndexMut<Pos> for LevelInfo {
-    fn index_mut(&mut self, i: Pos) -> &mut TileType {
-        &mut self.data[i]
+impl<'a> Index<Pos> for LevelInfo {
+    type Output = TileDef;
+    fn index(&self, i: Pos) -> &TileDef {
+        &self.tiles[self.data[i]]
     }
 }
 
 impl PosCollection for LevelInfo {
-    type Iter = <Matrix<TileType> as PosCollection>::Iter;
+    type Iter = <Matrix<TileId> as PosCollection>::Iter;
 
     fn iter_pos(&self) -> Self::Iter {
         self.data.iter_pos()
@@ -91,8 +93,45 @@ impl LevelInfo {
         Self::from_matrix(Matrix::new(width, height))
     }
 
-    pub fn from_matrix(data: Matrix<TileType>) -> Self {
-        LevelInfo { data }
+    /// Level made of the builtin tiles
+    pub fn from_matrix(data: Matrix<TileId>) -> Self {
+        Self::with_tiles(TileRegistry::builtin(), data)
+    }
+
+    pub fn with_tiles(tiles: Arc<TileRegistry>, data: Matrix<TileId>) -> Self {
+        LevelInfo {
+            data,
+            tiles,
+            id: NEXT_LEVEL_ID.fetch_add(1, Ordering::Relaxed),
+            changes: ChangeLog::default(),
+        }
+    }
+
+    pub fn tiles(&self) -> &Arc<TileRegistry> {
+        &self.tiles
+    }
+
+    pub fn tile(&self, p: Pos) -> TileId {
+        self.data[p]
+    }
+
+    /// Every call counts as a change of the tile, even if it stays the same
+    pub fn set(&mut self, p: Pos, tile: TileId) {
+        self.data[p] = tile;
+        self.changes.record(Rect::inclusive(p, p));
+    }
+
+    pub fn id(&self) -> LevelId {
+        self.id
+    }
+
+    pub fn version(&self) -> LevelVersion {
+        self.changes.version()
+    }
+
+    /// Terrain changes, see `ChangeLog::since`
+    pub fn changes_since(&self, since: LevelVersion) -> Option<impl Iterator<Item = Rect> + '_> {
+        self.changes.since(since)
     }
 
     pub fn with_dim(dim: Dim) -> Self {
@@ -107,8 +146,17 @@ impl LevelInfo {
         self.data.height()
     }
 
-    pub fn all_cells(&self) -> MatrixIter<TileType> {
-        self.data.iter()
+    pub fn all_cells(&self) -> impl Iterator<Item = (Pos, &TileDef)> {
+        self.data.iter().map(move |(p, &t)| (p, &self.tiles[t]))
+    }
+
+    pub fn view(&self, from: Pos, dim: Dim) -> MatrixView<TileId> {
+        self.data.view(from, dim)
+    }
+
+    pub fn stamp(&mut self, prefab: &MatrixView<TileId>, at: Pos) {
+        self.data.blit(prefab, at);
+        self.changes.record(Rect::with_dim(at, prefab.dim()));
     }
 
     pub fn is_valid(&self, p: Pos) -> bool {
@@ -123,3 +171,34 @@ impl LevelInfo {
         self.dim().max_pos()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn level() -> LevelInfo {
+        LevelInfo::new(10, 10)
+    }
+
+    #[test]
+    fn every_level_has_own_id() {
+        assert_ne!(level().id(), level().id());
+        let l = level();
+        assert_eq!(l.id(), l.clone().id());
+    }
+
+    #[test]
+    fn changes_are_logged_with_versions() {
+        let mut l = level();
+        assert_eq!(l.changes_since(0).unwrap().count(), 0);
+        let p = Pos { x: 3, y: 4 };
+        l.set(p, TileId::GROUND);
+        assert_eq!(l.version(), 1);
+        assert_eq!(
+            l.changes_since(0).unwrap().collect::<Vec<_>>(),
+            vec![Rect::inclusive(p, p)]
+        );
+        assert_eq!(l.changes_since(1).unwrap().count(), 0);
+        assert!(l.changes_since(2).is_none());
+    }
+}
@@ -1,21 +1,20 @@
-use std::ops::RangeInclusive;
-
 use crate::data::structures::level_data::HasWall;
 use crate::data::structures::pos::PosCollection;
+use crate::data::structures::rect::RectIter;
 use crate::data::structures::Dim;
 use crate::data::structures::DimIndex;
 use crate::data::structures::Pos;
+use crate::data::structures::Rect;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Room {
-    pub from: Pos,
-    pub to: Pos,
+    pub rect: Rect,
 }
 
 impl PosCollection for Room {
-    type Iter = <RangeInclusive<Pos> as PosCollection>::Iter;
+    type Iter = RectIter;
     fn iter_pos(&self) -> Self::Iter {
-        (self.from..=self.to).iter_pos()
+        self.rect.iter()
     }
 }
 
@@ -24,26 +23,21 @@ impl HasWall for Room {
 
     fn walls(&self) -> <Self as HasWall>::Iter {
         let mut walls = Vec::new();
-        for x in (self.from.x - 1)..=(self.to.x + 1) {
-            walls.push(Pos {
-                x,
-                y: self.from.y - 1,
-            });
-            walls.push(Pos {
-                x,
-                y: self.to.y + 1,
-            });
+        let last = self.rect.max_pos();
+        let north = self.rect.from.y.checked_sub(1);
+        let south = last.y.checked_add(1);
+        let west = self.rect.from.x.checked_sub(1);
+        let east = last.x.checked_add(1);
+        let from_x = west.unwrap_or(self.rect.from.x);
+        let to_x = east.unwrap_or(last.x);
+        for x in from_x..=to_x {
+            walls.extend(north.map(|y| Pos { x, y }));
+            walls.extend(south.map(|y| Pos { x, y }));
         }
 
-        for y in (self.from.y)..=(self.to.y) {
-            walls.push(Pos {
-                y,
-                x: self.from.x - 1,
-            });
-            walls.push(Pos {
-                y,
-                x: self.to.x + 1,
-            });
+        for y in (self.rect.from.y)..=(last.y) {
+            walls.extend(west.map(|x| Pos { x, y }));
+            walls.extend(east.map(|x| Pos { x, y }));
         }
         Box::new(walls.into_iter())
     }
@@ -56,45 +50,32 @@ impl Room {
         assert!(dim.width > 0);
         assert!(dim.height > 0);
         Room {
-            from,
-            to: Pos {
-                x: from.x + dim.width,
-                y: from.y + dim.height,
-            },
+            rect: Rect::with_dim(from, dim),
         }
     }
 
     pub fn center(&self) -> Pos {
-        Pos {
-            x: (self.from.x / 2) + (self.to.x / 2),
-            y: (self.from.y / 2) + (self.to.y / 2),
-        }
+        self.rect.center()
     }
 
     pub fn contains(&self, p: Pos) -> bool {
-        self.from.x <= p.x && self.to.x >= p.x && self.from.y <= p.y && self.to.y >= p.y
+        self.rect.contains(p)
     }
 
     pub fn contains_or_touches(&self, p: Pos) -> bool {
-        self.from.x - 1 <= p.x
-            && self.to.x + 1 >= p.x
-            && self.from.y - 1 <= p.y
-            && self.to.y + 1 >= p.y
+        self.rect.inflate(1).contains(p)
     }
 
     pub fn intersects(&self, other: &Room) -> bool {
-        (self.to.x >= other.from.x - 1)
-            && (other.to.x >= self.from.x - 1)
-            && (self.to.y >= other.from.y - 1)
-            && (other.to.y >= self.from.y - 1)
+        self.rect.inflate(1).intersects(&other.rect)
     }
 
     pub fn width(&self) -> DimIndex {
-        self.to.x - self.from.x
+        self.rect.width()
     }
 
     pub fn height(&self) -> DimIndex {
-        self.to.y - self.from.y
+        self.rect.height()
     }
 }
 
@@ -144,16 +125,19 @@ pub mod test {
         #[test]
         fn room_center_inside_room(room: Room) {
             prop_assert!(room.contains(room.center()));
-            prop_assert!(room.contains(room.from));
-            prop_assert!(room.contains(room.to));
-            prop_assert!(!room.contains(room.from.n().w()));
-            prop_assert!(!room.contains(room.to.s().e()));
+            let (from, to) = (room.rect.from, room.rect.max_pos());
+            prop_assert!(room.contains(from));
+            prop_assert!(room.contains(to));
+            let outer_from = from.n().and_then(Pos::w).unwrap();
+            let outer_to = to.s().and_then(Pos::e).unwrap();
+            prop_assert!(!room.contains(outer_from));
+            prop_assert!(!room.contains(outer_to));
 
             prop_assert!(room.contains_or_touches(room.center()));
-            prop_assert!(room.contains_or_touches(room.from));
-            prop_assert!(room.contains_or_touches(room.to));
-            prop_assert!(room.contains_or_touches(room.from.n().w()));
-            prop_assert!(room.contains_or_touches(room.to.s().e()));
+            prop_assert!(room.contains_or_touches(from));
+            prop_assert!(room.contains_or_touches(to));
+            prop_assert!(room.contains_or_touches(outer_from));
+            prop_assert!(room.contains_or_touches(outer_to));
         }
 
         #[test]
@@ -0,0 +1,174 @@
+use std::ops::AddAssign;
+
+use crate::data::structures::change_log::{ChangeLog, Version};
+use crate::data::structures::matrix::Matrix;
+use crate::data::structures::*;
+use crate::systems::render::Color;
+
+/// Tiles lit less than this can't be seen
+const DARK: f32 = 0.1;
+
+/// Light reaching a tile, as intensity of every colour channel. Channels start at 0 for darkness
+/// and can go over 1 where lights overlap
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Light {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Light {
+    pub fn full() -> Self {
+        Light {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+        }
+    }
+
+    pub fn from_color(color: Color, intensity: f32) -> Self {
+        let channel = |c: u8| f32::from(c) / 255.0 * intensity;
+        Light {
+            r: channel(color.r),
+            g: channel(color.g),
+            b: channel(color.b),
+        }
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.r.max(self.g).max(self.b)
+    }
+
+    pub fn is_dark(&self) -> bool {
+        self.intensity() < DARK
+    }
+
+    /// Colour of a glyph under this light. Even dim light keeps it brighter than a remembered one
+    pub fn tint(&self, color: Color) -> Color {
+        let channel = |c: u8, l: f32| (f32::from(c) * (0.5 + 0.5 * l.min(1.0))) as u8;
+        Color {
+            r: channel(color.r, self.r),
+            g: channel(color.g, self.g),
+            b: channel(color.b, self.b),
+        }
+    }
+}
+
+impl AddAssign for Light {
+    fn add_assign(&mut self, other: Light) {
+        self.r += other.r;
+        self.g += other.g;
+        self.b += other.b;
+    }
+}
+
+/// Light level of every tile, calculated by the `Lighting` system. Until it runs everything
+/// counts as fully lit
+#[derive(Debug, Default)]
+pub struct LightMap {
+    lights: Option<Matrix<Light>>,
+    changes: ChangeLog,
+}
+
+impl LightMap {
+    pub fn light_at(&self, pos: Pos) -> Light {
+        match self.lights {
+            Some(ref lights) if lights.is_valid(pos) => lights[pos],
+            Some(_) => Light::default(),
+            None => Light::full(),
+        }
+    }
+
+    pub fn is_lit(&self, pos: Pos) -> bool {
+        !self.light_at(pos).is_dark()
+    }
+
+    /// Changes every time lights are updated with something new
+    pub fn version(&self) -> Version {
+        self.changes.version()
+    }
+
+    /// Lit areas that changed, see `ChangeLog::since`
+    pub fn changes_since(&self, since: Version) -> Option<impl Iterator<Item = Rect> + '_> {
+        self.changes.since(since)
+    }
+
+    /// Darkens a level of `dim`, which counts as a change of all of it
+    pub fn reset(&mut self, dim: Dim) {
+        self.lights = Some(Matrix::tabulate(dim, |_| Light::default()));
+        self.changes.record(Rect::with_dim(Pos { x: 0, y: 0 }, dim));
+    }
+
+    /// Sets light of every tile of `area`, recording a change only if some tile got new light
+    pub fn update<F>(&mut self, area: Rect, light: F)
+    where
+        F: Fn(Pos) -> Light,
+    {
+        let lights = self.lights.as_mut().expect("LightMap::update before reset");
+        let mut changed = false;
+        for p in area.iter() {
+            let new = light(p);
+            if lights[p] != new {
+                lights[p] = new;
+                changed = true;
+            }
+        }
+        if changed {
+            self.changes.record(area);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn everything_is_lit_until_calculated() {
+        let mut map = LightMap::default();
+        assert!(map.is_lit(Pos { x: 5, y: 5 }));
+        let dim = Dim {
+            width: 2,
+            height: 1,
+        };
+        let red = |p: Pos| Light::from_color(Color { r: 255, g: 0, b: 0 }, f32::from(p.x));
+        map.reset(dim);
+        let all = Rect::with_dim(Pos { x: 0, y: 0 }, dim);
+        map.update(all, red);
+        assert!(!map.is_lit(Pos { x: 0, y: 0 }));
+        assert!(map.is_lit(Pos { x: 1, y: 0 }));
+        assert!(!map.is_lit(Pos { x: 5, y: 5 }));
+        let version = map.version();
+        map.update(all, red);
+        assert_eq!(version, map.version());
+    }
+
+    #[test]
+    fn changed_areas_are_logged_with_versions() {
+        let mut map = LightMap::default();
+        map.reset(Dim {
+            width: 4,
+            height: 4,
+        });
+        let version = map.version();
+        let area = Rect::inclusive(Pos { x: 1, y: 1 }, Pos { x: 2, y: 2 });
+        map.update(area, |_| Light::full());
+        assert_eq!(
+            map.changes_since(version).unwrap().collect::<Vec<_>>(),
+            vec![area]
+        );
+        assert_eq!(map.changes_since(map.version()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn tint_keeps_glyph_visible() {
+        let grey = Color {
+            r: 200,
+            g: 200,
+            b: 200,
+        };
+        assert_eq!(Light::full().tint(grey), grey);
+        let red = Light::from_color(Color { r: 255, g: 0, b: 0 }, 1.0).tint(grey);
+        assert_eq!((red.r, red.g, red.b), (200, 100, 100));
+    }
+}
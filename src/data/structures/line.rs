@@ -0,0 +1,123 @@
+use crate::data::structures::pos::PosCollection;
+use crate::data::structures::DimIndex;
+use crate::data::structures::Pos;
+
+/// Bresenham path between two positions, both ends included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Line {
+    pub from: Pos,
+    pub to: Pos,
+}
+
+impl Line {
+    pub fn new(from: Pos, to: Pos) -> Self {
+        Line { from, to }
+    }
+
+    pub fn iter(&self) -> LineIter {
+        let (x0, y0) = (i32::from(self.from.x), i32::from(self.from.y));
+        let (x1, y1) = (i32::from(self.to.x), i32::from(self.to.y));
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        LineIter {
+            x: x0,
+            y: y0,
+            to_x: x1,
+            to_y: y1,
+            dx,
+            dy,
+            sx: if x0 < x1 { 1 } else { -1 },
+            sy: if y0 < y1 { 1 } else { -1 },
+            err: dx + dy,
+            done: false,
+        }
+    }
+}
+
+impl PosCollection for Line {
+    type Iter = LineIter;
+
+    fn iter_pos(&self) -> LineIter {
+        self.iter()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LineIter {
+    x: i32,
+    y: i32,
+    to_x: i32,
+    to_y: i32,
+    dx: i32,
+    dy: i32,
+    sx: i32,
+    sy: i32,
+    err: i32,
+    done: bool,
+}
+
+impl Iterator for LineIter {
+    type Item = Pos;
+
+    fn next(&mut self) -> Option<Pos> {
+        if self.done {
+            return None;
+        }
+        let current = Pos {
+            x: self.x as DimIndex,
+            y: self.y as DimIndex,
+        };
+        if self.x == self.to_x && self.y == self.to_y {
+            self.done = true;
+        } else {
+            let e2 = 2 * self.err;
+            if e2 >= self.dy {
+                self.err += self.dy;
+                self.x += self.sx;
+            }
+            if e2 <= self.dx {
+                self.err += self.dx;
+                self.y += self.sy;
+            }
+        }
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use proptest::prelude::*;
+    use proptest::{prop_assert, prop_assert_eq, proptest, proptest_helper};
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn line_connects_ends(from: Pos, to: Pos) {
+            let line = Line::new(from, to).iter_pos().collect::<Vec<_>>();
+            prop_assert_eq!(Some(&from), line.first());
+            prop_assert_eq!(Some(&to), line.last());
+            prop_assert_eq!(from.chebyshev_distance(to) as usize + 1, line.len());
+        }
+
+        #[test]
+        fn line_is_continuous(from: Pos, to: Pos) {
+            let line = Line::new(from, to);
+            for (p1, p2) in line.iter_pos().tuple_windows() {
+                prop_assert_eq!(1, p1.chebyshev_distance(p2));
+            }
+            prop_assert_eq!(line.iter_pos().count(), line.iter_pos().unique().count());
+        }
+
+        #[test]
+        fn line_stays_in_bounding_box(from: Pos, to: Pos) {
+            for p in Line::new(from, to).iter_pos() {
+                prop_assert!(p.x >= DimIndex::min(from.x, to.x));
+                prop_assert!(p.x <= DimIndex::max(from.x, to.x));
+                prop_assert!(p.y >= DimIndex::min(from.y, to.y));
+                prop_assert!(p.y <= DimIndex::max(from.y, to.y));
+            }
+        }
+    }
+}
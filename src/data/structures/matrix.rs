@@ -1,11 +1,13 @@
+use std::collections::VecDeque;
 use std::iter::Enumerate;
 use std::ops::Index;
 use std::ops::IndexMut;
-use std::ops::Range;
 use std::slice::Iter;
 
 pub use super::dim::*;
 pub use super::pos::*;
+use super::rect::{Rect, RectIter};
+use super::{ALL_DIRS, CARDINAL_DIRS};
 
 type InternalIndex = usize;
 
@@ -85,6 +87,112 @@ impl<T> Matrix<T> {
     }
 }
 
+impl<T> Matrix<T> {
+    fn fits(&self, from: Pos, dim: Dim) -> bool {
+        from.x as InternalIndex + dim.width as InternalIndex <= self.width as InternalIndex
+            && from.y as InternalIndex + dim.height as InternalIndex <= self.height as InternalIndex
+    }
+
+    pub fn view(&self, from: Pos, dim: Dim) -> MatrixView<T> {
+        assert!(self.fits(from, dim));
+        MatrixView {
+            matrix: self,
+            from,
+            dim,
+        }
+    }
+
+    pub fn view_mut(&mut self, from: Pos, dim: Dim) -> MatrixViewMut<T> {
+        assert!(self.fits(from, dim));
+        MatrixViewMut {
+            matrix: self,
+            from,
+            dim,
+        }
+    }
+
+    pub fn as_view(&self) -> MatrixView<T> {
+        self.view(Pos::default(), self.dim())
+    }
+}
+
+impl<T> Matrix<T> {
+    pub fn map<U, F>(&self, f: F) -> Matrix<U>
+    where
+        F: FnMut(&T) -> U,
+    {
+        Matrix {
+            width: self.width,
+            height: self.height,
+            data: self.data.iter().map(f).collect(),
+        }
+    }
+
+    pub fn zip_with<U, V, F>(&self, other: &Matrix<U>, mut f: F) -> Matrix<V>
+    where
+        F: FnMut(&T, &U) -> V,
+    {
+        assert_eq!(self.width, other.width);
+        assert_eq!(self.height, other.height);
+        Matrix {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .zip(other.data.iter())
+                .map(|(t, u)| f(t, u))
+                .collect(),
+        }
+    }
+
+    pub fn neighbors4(&self, pos: Pos) -> impl Iterator<Item = Pos> {
+        let dim = self.dim();
+        CARDINAL_DIRS.iter().filter_map(move |&d| dim.step(pos, d))
+    }
+
+    pub fn neighbors8(&self, pos: Pos) -> impl Iterator<Item = Pos> {
+        let dim = self.dim();
+        ALL_DIRS.iter().filter_map(move |&d| dim.step(pos, d))
+    }
+
+    pub fn count_where<F>(&self, mut f: F) -> usize
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.data.iter().filter(|t| f(t)).count()
+    }
+
+    /// Marks every cell reachable from `start` through 4-connected cells matching `f`
+    pub fn flood_fill<F>(&self, start: Pos, mut f: F) -> Matrix<bool>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut filled: Matrix<bool> = Matrix::new(self.width, self.height);
+        if !f(&self[start]) {
+            return filled;
+        }
+        filled[start] = true;
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some(p) = queue.pop_front() {
+            for n in self.neighbors4(p) {
+                if !filled[n] && f(&self[n]) {
+                    filled[n] = true;
+                    queue.push_back(n);
+                }
+            }
+        }
+        filled
+    }
+}
+
+impl<T: Clone> Matrix<T> {
+    pub fn blit(&mut self, src: &MatrixView<T>, at: Pos) {
+        self.view_mut(at, src.dim()).copy_from(src);
+    }
+}
+
 impl<T: Default + Clone> Matrix<T> {
     pub fn new(width: DimIndex, height: DimIndex) -> Self {
         let data_size: InternalIndex = width as InternalIndex * height as InternalIndex;
@@ -113,11 +221,11 @@ impl<'a, T> IndexMut<Pos> for Matrix<T> {
 }
 
 impl<T> HasDim for Matrix<T> {
-    fn width(&self) -> u8 {
+    fn width(&self) -> DimIndex {
         self.width
     }
 
-    fn height(&self) -> u8 {
+    fn height(&self) -> DimIndex {
         self.height
     }
 }
@@ -139,16 +247,182 @@ impl<'a, T> Iterator for MatrixIter<'a, T> {
 }
 
 impl<T> PosCollection for Matrix<T> {
-    type Iter = <Range<Pos> as PosCollection>::Iter;
+    type Iter = RectIter;
 
     fn iter_pos(&self) -> <Self as PosCollection>::Iter {
-        let from = Pos { x: 0, y: 0 };
-        let to = Pos {
-            x: self.width,
-            y: self.height,
-        };
+        Rect::with_dim(Pos::default(), self.dim()).iter()
+    }
+}
+
+fn offset(from: Pos, dim: Dim, p: Pos) -> Pos {
+    assert!(dim.is_valid(p));
+    Pos {
+        x: from.x + p.x,
+        y: from.y + p.y,
+    }
+}
+
+/// Read-only rectangular window into a `Matrix`, indexed relatively to its top-left corner
+#[derive(Debug)]
+pub struct MatrixView<'a, T: 'a> {
+    matrix: &'a Matrix<T>,
+    from: Pos,
+    dim: Dim,
+}
+
+impl<'a, T> Clone for MatrixView<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for MatrixView<'a, T> {}
+
+impl<'a, T> MatrixView<'a, T> {
+    pub fn origin(&self) -> Pos {
+        self.from
+    }
+
+    pub fn dim(&self) -> Dim {
+        self.dim
+    }
+
+    pub fn iter(&self) -> MatrixViewIter<'a, T> {
+        MatrixViewIter {
+            view: *self,
+            next: 0,
+        }
+    }
+}
+
+impl<'a, T: Clone> MatrixView<'a, T> {
+    pub fn to_matrix(&self) -> Matrix<T> {
+        Matrix::tabulate(self.dim, |p| self[p].clone())
+    }
+}
+
+impl<'a, T> Index<Pos> for MatrixView<'a, T> {
+    type Output = T;
+
+    fn index(&self, p: Pos) -> &T {
+        &self.matrix[offset(self.from, self.dim, p)]
+    }
+}
+
+impl<'a, T> HasDim for MatrixView<'a, T> {
+    fn width(&self) -> DimIndex {
+        self.dim.width
+    }
+
+    fn height(&self) -> DimIndex {
+        self.dim.height
+    }
+}
 
-        (from..to).iter_pos()
+#[derive(Debug)]
+pub struct MatrixViewIter<'a, T: 'a> {
+    view: MatrixView<'a, T>,
+    next: InternalIndex,
+}
+
+impl<'a, T> Iterator for MatrixViewIter<'a, T> {
+    type Item = (Pos, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= Matrix::<T>::max_index(&self.view.dim) {
+            return None;
+        }
+        let pos = Matrix::<T>::index_to_pos(&self.view.dim, self.next);
+        self.next += 1;
+        let matrix: &'a Matrix<T> = self.view.matrix;
+        Some((pos, &matrix[offset(self.view.from, self.view.dim, pos)]))
+    }
+}
+
+/// Mutable rectangular window into a `Matrix`, indexed relatively to its top-left corner
+#[derive(Debug)]
+pub struct MatrixViewMut<'a, T: 'a> {
+    matrix: &'a mut Matrix<T>,
+    from: Pos,
+    dim: Dim,
+}
+
+impl<'a, T> MatrixViewMut<'a, T> {
+    pub fn origin(&self) -> Pos {
+        self.from
+    }
+
+    pub fn dim(&self) -> Dim {
+        self.dim
+    }
+
+    pub fn as_view(&self) -> MatrixView<T> {
+        MatrixView {
+            matrix: self.matrix,
+            from: self.from,
+            dim: self.dim,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Pos, &mut T)> + '_ {
+        let width = self.matrix.width as InternalIndex;
+        let from_x = self.from.x as InternalIndex;
+        let to_x = from_x + self.dim.width as InternalIndex;
+        self.matrix
+            .data
+            .chunks_mut(width)
+            .skip(self.from.y as InternalIndex)
+            .take(self.dim.height as InternalIndex)
+            .enumerate()
+            .flat_map(move |(y, row)| {
+                row[from_x..to_x].iter_mut().enumerate().map(move |(x, t)| {
+                    let pos = Pos {
+                        x: x as DimIndex,
+                        y: y as DimIndex,
+                    };
+                    (pos, t)
+                })
+            })
+    }
+}
+
+impl<'a, T: Clone> MatrixViewMut<'a, T> {
+    pub fn fill(&mut self, value: T) {
+        for (_, t) in self.iter_mut() {
+            *t = value.clone();
+        }
+    }
+
+    pub fn copy_from(&mut self, src: &MatrixView<T>) {
+        assert_eq!(self.dim.width, src.dim.width);
+        assert_eq!(self.dim.height, src.dim.height);
+        for (p, t) in self.iter_mut() {
+            *t = src[p].clone();
+        }
+    }
+}
+
+impl<'a, T> Index<Pos> for MatrixViewMut<'a, T> {
+    type Output = T;
+
+    fn index(&self, p: Pos) -> &T {
+        &self.matrix[offset(self.from, self.dim, p)]
+    }
+}
+
+impl<'a, T> IndexMut<Pos> for MatrixViewMut<'a, T> {
+    fn index_mut(&mut self, p: Pos) -> &mut T {
+        &mut self.matrix[offset(self.from, self.dim, p)]
+    }
+}
+
+impl<'a, T> HasDim for MatrixViewMut<'a, T> {
+    fn width(&self) -> DimIndex {
+        self.dim.width
+    }
+
+    fn height(&self) -> DimIndex {
+        self.dim.height
     }
 }
 
@@ -159,15 +433,17 @@ mod test {
     use itertools::*;
     use proptest::prelude::*;
     use proptest::{
-        prop_assert, prop_assert_eq, prop_assume, prop_compose, proptest, proptest_helper,
+        prop_assert, prop_assert_eq, prop_assert_ne, prop_assume, prop_compose, proptest,
+        proptest_helper,
     };
 
     use super::*;
+    use crate::data::structures::pos::test::MAX_DIM;
 
     impl<T: Debug + Default + Clone> Arbitrary for Matrix<T> {
         type Parameters = ();
         fn arbitrary_with(_args: <Self as Arbitrary>::Parameters) -> <Self as Arbitrary>::Strategy {
-            matrix(DimIndex::max_value(), DimIndex::max_value())
+            matrix(MAX_DIM.width, MAX_DIM.height)
         }
         type Strategy = BoxedStrategy<Matrix<T>>;
     }
@@ -188,7 +464,7 @@ mod test {
     }
 
     fn matrix_and_pos<T: 'static + Debug + Default + Clone>() -> BoxedStrategy<(Matrix<T>, Pos)> {
-        matrix_and_pos_limit(DimIndex::max_value(), DimIndex::max_value())
+        matrix_and_pos_limit(MAX_DIM.width, MAX_DIM.height)
     }
 
     fn matrix_and_pos_limit<T: 'static + Debug + Default + Clone>(
@@ -203,7 +479,112 @@ mod test {
             .boxed()
     }
 
+    fn matrix_and_rect() -> BoxedStrategy<(Matrix<Pos>, Pos, Dim)> {
+        matrix_and_pos::<bool>()
+            .prop_flat_map(|(m, from)| {
+                let m = Matrix::tabulate(m.dim(), |p| p);
+                let dim = (1..=m.width - from.x, 1..=m.height - from.y)
+                    .prop_map(|(width, height)| Dim { width, height });
+                (Just(m), Just(from), dim)
+            })
+            .boxed()
+    }
+
     proptest! {
+        #[test]
+        fn map_keeps_positions(m: Matrix<bool>) {
+            let mapped = Matrix::tabulate(m.dim(), |p| p).map(|p| p.x);
+            prop_assert_eq!(m.dim().width, mapped.width());
+            prop_assert_eq!(m.dim().height, mapped.height());
+            for (p, x) in mapped.iter() {
+                prop_assert_eq!(p.x, *x);
+            }
+        }
+
+        #[test]
+        fn zip_with_combines_same_cells(m: Matrix<bool>) {
+            let xs = Matrix::tabulate(m.dim(), |p| p.x);
+            let ys = Matrix::tabulate(m.dim(), |p| p.y);
+            let zipped = xs.zip_with(&ys, |&x, &y| Pos { x, y });
+            for (p, v) in zipped.iter() {
+                prop_assert_eq!(p, *v);
+            }
+        }
+
+        #[test]
+        fn neighbors_are_adjacent_and_valid((m, pos) in matrix_and_pos::<bool>()) {
+            let n4 = m.neighbors4(pos).collect::<Vec<_>>();
+            let n8 = m.neighbors8(pos).collect::<Vec<_>>();
+            prop_assert!(n4.len() <= 4);
+            prop_assert!(n8.len() <= 8);
+            for n in &n4 {
+                prop_assert!(n8.contains(n));
+                let dx = (i32::from(n.x) - i32::from(pos.x)).abs();
+                let dy = (i32::from(n.y) - i32::from(pos.y)).abs();
+                prop_assert_eq!(1, dx + dy);
+            }
+            for n in &n8 {
+                prop_assert!(m.is_valid(*n));
+                prop_assert_ne!(pos, *n);
+                prop_assert!((i32::from(n.x) - i32::from(pos.x)).abs() <= 1);
+                prop_assert!((i32::from(n.y) - i32::from(pos.y)).abs() <= 1);
+            }
+            prop_assert_eq!(n8.len(), n8.iter().unique().count());
+        }
+
+        #[test]
+        fn flood_fill_stops_at_walls((m, pos) in matrix_and_pos::<bool>()) {
+            let wall_x = pos.x;
+            let open = Matrix::tabulate(m.dim(), |p| p.x != wall_x);
+            let start = Pos { x: 0, y: pos.y };
+            let filled = open.flood_fill(start, |&o| o);
+            prop_assert_eq!(
+                wall_x as usize * m.height as usize,
+                filled.count_where(|&f| f)
+            );
+            for (p, f) in filled.iter() {
+                prop_assert_eq!(p.x < wall_x, *f);
+            }
+        }
+
+        #[test]
+        fn view_iterates_only_its_rect((m, from, dim) in matrix_and_rect()) {
+            let view = m.view(from, dim);
+            prop_assert_eq!(
+                dim.width as InternalIndex * dim.height as InternalIndex,
+                view.iter().count()
+            );
+            for (p, v) in view.iter() {
+                prop_assert!(view.is_valid(p));
+                prop_assert_eq!(Pos { x: from.x + p.x, y: from.y + p.y }, *v);
+                prop_assert_eq!(view[p], *v);
+            }
+        }
+
+        #[test]
+        fn fill_touches_only_view((m, from, dim) in matrix_and_rect()) {
+            let mut filled: Matrix<bool> = Matrix::new(m.width, m.height);
+            filled.view_mut(from, dim).fill(true);
+            for (p, v) in filled.iter() {
+                let inside = p.x >= from.x
+                    && p.x < from.x + dim.width
+                    && p.y >= from.y
+                    && p.y < from.y + dim.height;
+                prop_assert_eq!(inside, *v);
+            }
+        }
+
+        #[test]
+        fn blit_copies_view((m, from, dim) in matrix_and_rect()) {
+            let cropped = m.view(from, dim).to_matrix();
+            let mut target: Matrix<Pos> = Matrix::new(m.width, m.height);
+            target.blit(&cropped.as_view(), from);
+            for (p, v) in target.view(from, dim).iter() {
+                prop_assert_eq!(cropped[p], *v);
+                prop_assert_eq!(m[Pos { x: from.x + p.x, y: from.y + p.y }], *v);
+            }
+        }
+
         #[test]
         fn max_pos_no_overflow(m: Matrix<bool>) {
             let _ = m.max_pos();
@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+
+const MAX_MESSAGES: usize = 100;
+
+/// Messages shown to the player, oldest first. Only the latest `MAX_MESSAGES` are kept
+#[derive(Debug, Default)]
+pub struct MessageLog {
+    messages: VecDeque<String>,
+}
+
+impl MessageLog {
+    pub fn push<S: Into<String>>(&mut self, message: S) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message.into());
+    }
+
+    /// Up to `n` latest messages, oldest first
+    pub fn latest(&self, n: usize) -> impl Iterator<Item = &str> {
+        let skip = self.messages.len().saturating_sub(n);
+        self.messages.iter().skip(skip).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_latest_messages() {
+        let mut log = MessageLog::default();
+        for i in 0..MAX_MESSAGES + 5 {
+            log.push(i.to_string());
+        }
+        assert_eq!(log.len(), MAX_MESSAGES);
+        let latest: Vec<_> = log.latest(2).collect();
+        assert_eq!(latest, vec!["103", "104"]);
+    }
+}
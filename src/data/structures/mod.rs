@@ -1,18 +1,39 @@
 use std::convert::From;
+use std::ops::Add;
 
+pub use self::dungeon::{Depth, Dungeon, Floor};
 pub use self::level_data::*;
+pub use self::light_map::{Light, LightMap};
 pub use self::matrix::{Dim, DimIndex, HasDim, Pos};
+pub use self::message_log::MessageLog;
+pub use self::rect::Rect;
+pub use self::spatial_index::SpatialIndex;
+pub use self::tiles::{TileDef, TileId, TileRegistry};
 use specs::Entity;
 
+pub mod bit_matrix;
+pub mod change_log;
 pub mod dim;
+pub mod dungeon;
 mod level_data;
+pub mod light_map;
+pub mod line;
 pub mod matrix;
+pub mod message_log;
 pub mod pos;
+pub mod rect;
+pub mod shapes;
+pub mod spatial_index;
+pub mod tiles;
 pub mod world_data;
 
 #[derive(Debug)]
 pub enum GameCommand {
     Exit,
+    Look,
+    Confirm,
+    Descend,
+    Ascend,
 }
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ActorCommand {
@@ -20,16 +41,53 @@ pub enum ActorCommand {
     MeleeAttack { pos: Pos, target: Entity },
 }
 
+/// Player behaviour spanning many turns, planned one step per tick.
+/// `path` holds the positions left to walk with the next one last, empty until it's planned
+#[derive(Debug, Clone, PartialEq)]
+pub enum Activity {
+    Explore { path: Vec<Pos> },
+    Travel { target: Pos, path: Vec<Pos> },
+}
+
+impl Activity {
+    pub fn explore() -> Activity {
+        Activity::Explore { path: vec![] }
+    }
+    pub fn travel(target: Pos) -> Activity {
+        Activity::Travel {
+            target,
+            path: vec![],
+        }
+    }
+}
+
+/// Tile picked in look mode, directions move it instead of the player while it's active
+#[derive(Debug, Default)]
+pub struct LookCursor(pub Option<Pos>);
+
 #[derive(Debug)]
 pub enum Command {
     GameCommand(GameCommand),
     PlayerCommand(ActorCommand),
+    PlayerActivity(Activity),
 }
 
 impl Command {
     pub fn exit() -> Command {
         Command::GameCommand(GameCommand::Exit)
     }
+    pub fn look() -> Command {
+        Command::GameCommand(GameCommand::Look)
+    }
+    pub fn confirm() -> Command {
+        Command::GameCommand(GameCommand::Confirm)
+    }
+    pub fn descend() -> Command {
+        Command::GameCommand(GameCommand::Descend)
+    }
+    pub fn ascend() -> Command {
+        Command::GameCommand(GameCommand::Ascend)
+    }
     fn move_dir(dir: Dir) -> Command {
         Command::PlayerCommand(ActorCommand::Move(dir))
     }
@@ -45,6 +103,21 @@ impl Command {
     pub fn north() -> Command {
         Command::move_dir(N)
     }
+    pub fn north_east() -> Command {
+        Command::move_dir(NE)
+    }
+    pub fn north_west() -> Command {
+        Command::move_dir(NW)
+    }
+    pub fn south_east() -> Command {
+        Command::move_dir(SE)
+    }
+    pub fn south_west() -> Command {
+        Command::move_dir(SW)
+    }
+    pub fn explore() -> Command {
+        Command::PlayerActivity(Activity::explore())
+    }
 }
 
 pub const N: Dir = Dir {
@@ -63,6 +136,25 @@ pub const E: Dir = Dir {
     ns: MoveDir::Zero,
     ew: MoveDir::Plus,
 };
+pub const NE: Dir = Dir {
+    ns: MoveDir::Minus,
+    ew: MoveDir::Plus,
+};
+pub const NW: Dir = Dir {
+    ns: MoveDir::Minus,
+    ew: MoveDir::Minus,
+};
+pub const SE: Dir = Dir {
+    ns: MoveDir::Plus,
+    ew: MoveDir::Plus,
+};
+pub const SW: Dir = Dir {
+    ns: MoveDir::Plus,
+    ew: MoveDir::Minus,
+};
+
+pub const CARDINAL_DIRS: [Dir; 4] = [N, E, S, W];
+pub const ALL_DIRS: [Dir; 8] = [N, NE, E, SE, S, SW, W, NW];
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Dir {
@@ -77,12 +169,44 @@ pub enum MoveDir {
     Plus,
 }
 
+impl Add<Dir> for Pos {
+    type Output = Option<Pos>;
+
+    fn add(self, other: Dir) -> Option<Pos> {
+        let x = other.ew.apply(self.x)?;
+        let y = other.ns.apply(self.y)?;
+        Some(Pos { x, y })
+    }
+}
+
+impl Dir {
+    /// Direction of a single step between adjacent positions
+    pub fn between(from: Pos, to: Pos) -> Option<Dir> {
+        if from == to || from.chebyshev_distance(to) != 1 {
+            return None;
+        }
+        let towards = |a: DimIndex, b: DimIndex| {
+            if a < b {
+                MoveDir::Plus
+            } else if a > b {
+                MoveDir::Minus
+            } else {
+                MoveDir::Zero
+            }
+        };
+        Some(Dir {
+            ns: towards(from.y, to.y),
+            ew: towards(from.x, to.x),
+        })
+    }
+}
+
 impl MoveDir {
-    pub fn apply(self, i: DimIndex) -> DimIndex {
+    pub fn apply(self, i: DimIndex) -> Option<DimIndex> {
         match self {
-            MoveDir::Minus => i - 1,
-            MoveDir::Plus => i + 1,
-            MoveDir::Zero => i,
+            MoveDir::Minus => i.checked_sub(1),
+            MoveDir::Plus => i.checked_add(1),
+            MoveDir::Zero => Some(i),
         }
     }
     pub fn to_int(self) -> i8 {
@@ -1,9 +1,12 @@
-use itertools::iproduct;
 use std::cmp::Ordering;
+use std::iter::{once, Once};
 use std::ops::Range;
 use std::ops::RangeInclusive;
+use std::vec;
 
-pub type DimIndex = u8;
+use crate::data::structures::rect::{Rect, RectIter};
+
+pub type DimIndex = u16;
 
 #[derive(Debug, PartialEq, Copy, Clone, Eq, Hash, Default)]
 pub struct Pos {
@@ -13,83 +16,57 @@ pub struct Pos {
 
 impl PartialOrd for Pos {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let self_w: u16 = u16::from(self.x) + u16::from(self.y);
-        let other_w: u16 = u16::from(other.x) + u16::from(other.y);
-        self_w.partial_cmp(&other_w)
+        Some(self.cmp(other))
     }
 }
 
+/// Row-major order: by row first, then by column
 impl Ord for Pos {
     fn cmp(&self, other: &Self) -> Ordering {
-        let self_w: u16 = u16::from(self.x) + u16::from(self.y);
-        let other_w: u16 = u16::from(other.x) + u16::from(other.y);
-        self_w.cmp(&other_w)
+        (self.y, self.x).cmp(&(other.y, other.x))
     }
 }
 
 impl Pos {
-    pub fn n(self) -> Pos {
-        Pos {
-            y: self.y - 1,
-            ..self
-        }
+    pub fn n(self) -> Option<Pos> {
+        self.y.checked_sub(1).map(|y| Pos { y, ..self })
     }
-    pub fn s(self) -> Pos {
-        Pos {
-            y: self.y + 1,
-            ..self
-        }
+    pub fn s(self) -> Option<Pos> {
+        self.y.checked_add(1).map(|y| Pos { y, ..self })
     }
-    pub fn w(self) -> Pos {
-        Pos {
-            x: self.x - 1,
-            ..self
-        }
+    pub fn w(self) -> Option<Pos> {
+        self.x.checked_sub(1).map(|x| Pos { x, ..self })
     }
-    pub fn e(self) -> Pos {
-        Pos {
-            x: self.x + 1,
-            ..self
-        }
+    pub fn e(self) -> Option<Pos> {
+        self.x.checked_add(1).map(|x| Pos { x, ..self })
     }
-}
-
-pub struct PosRange {
-    from: Pos,
-    to: Pos,
-}
-
-impl PosRange {
-    fn new(p1: Pos, p2: Pos) -> Self {
-        let from = Pos {
-            x: DimIndex::min(p1.x, p2.x),
-            y: DimIndex::min(p1.y, p2.y),
-        };
-        let to = Pos {
-            x: DimIndex::max(p1.x, p2.x),
-            y: DimIndex::max(p1.y, p2.y),
-        };
 
-        PosRange { from, to }
+    fn deltas(self, other: Pos) -> (DimIndex, DimIndex) {
+        fn delta(a: DimIndex, b: DimIndex) -> DimIndex {
+            if a > b {
+                a - b
+            } else {
+                b - a
+            }
+        }
+        (delta(self.x, other.x), delta(self.y, other.y))
     }
 
-    fn iter_inclusive(self) -> impl Iterator<Item = Pos> {
-        iproduct!(self.from.x..=self.to.x, self.from.y..=self.to.y).map(|(x, y)| Pos { x, y })
+    /// Number of king moves between two positions
+    pub fn chebyshev_distance(self, other: Pos) -> DimIndex {
+        let (dx, dy) = self.deltas(other);
+        DimIndex::max(dx, dy)
     }
 
-    fn iter(self) -> impl Iterator<Item = Pos> {
-        iproduct!(self.from.x..self.to.x, self.from.y..self.to.y).map(|(x, y)| Pos { x, y })
+    /// Number of orthogonal moves between two positions
+    pub fn manhattan_distance(self, other: Pos) -> u32 {
+        let (dx, dy) = self.deltas(other);
+        u32::from(dx) + u32::from(dy)
     }
-}
-
-impl IntoIterator for PosRange {
-    type Item = Pos;
-    type IntoIter = Box<dyn Iterator<Item = Pos>>;
 
-    fn into_iter(self) -> <Self as IntoIterator>::IntoIter {
-        let iter =
-            iproduct!(self.from.x..=self.to.x, self.from.y..=self.to.y).map(|(x, y)| Pos { x, y });
-        Box::new(iter)
+    pub fn euclidean_distance(self, other: Pos) -> f64 {
+        let (dx, dy) = self.deltas(other);
+        (f64::from(dx).powi(2) + f64::from(dy).powi(2)).sqrt()
     }
 }
 
@@ -100,18 +77,34 @@ pub trait PosCollection {
 }
 
 impl PosCollection for Range<Pos> {
-    type Iter = Box<dyn Iterator<Item = Pos>>;
+    type Iter = RectIter;
 
     fn iter_pos(&self) -> Self::Iter {
-        Box::new(PosRange::new(self.start, self.end).iter())
+        Rect::new(self.start, self.end).iter()
     }
 }
 
 impl PosCollection for RangeInclusive<Pos> {
-    type Iter = <PosRange as IntoIterator>::IntoIter;
+    type Iter = RectIter;
 
     fn iter_pos(&self) -> Self::Iter {
-        Box::new(PosRange::new(*self.start(), *self.end()).iter_inclusive())
+        Rect::inclusive(*self.start(), *self.end()).iter()
+    }
+}
+
+impl PosCollection for Pos {
+    type Iter = Once<Pos>;
+
+    fn iter_pos(&self) -> Self::Iter {
+        once(*self)
+    }
+}
+
+impl PosCollection for Vec<Pos> {
+    type Iter = vec::IntoIter<Pos>;
+
+    fn iter_pos(&self) -> Self::Iter {
+        self.clone().into_iter()
     }
 }
 
@@ -119,7 +112,7 @@ impl PosCollection for RangeInclusive<Pos> {
 pub mod test {
     use itertools::*;
     use proptest::prelude::*;
-    use proptest::{prop_assert, prop_assert_eq, prop_compose, proptest, proptest_helper};
+    use proptest::{prop_assert, prop_assert_eq, proptest, proptest_helper};
 
     use super::*;
     use crate::data::structures::Dim;
@@ -135,8 +128,8 @@ pub mod test {
     }
 
     pub const MAX_DIM: Dim = Dim {
-        width: DimIndex::max_value(),
-        height: DimIndex::max_value(),
+        width: 512,
+        height: 512,
     };
 
     pub const SMALL_DIM: Dim = Dim {
@@ -164,13 +157,68 @@ pub mod test {
             .boxed()
     }
 
-    prop_compose! {
-        fn arb_pos()(x: DimIndex, y: DimIndex) -> Pos {
-            Pos {x, y}
-        }
+    fn arb_pos() -> BoxedStrategy<Pos> {
+        pos_in_dim(MAX_DIM)
+    }
+
+    #[test]
+    fn steps_never_wrap() {
+        let min = Pos { x: 0, y: 0 };
+        let max = Pos {
+            x: DimIndex::max_value(),
+            y: DimIndex::max_value(),
+        };
+        assert_eq!(None, min.n());
+        assert_eq!(None, min.w());
+        assert_eq!(None, max.s());
+        assert_eq!(None, max.e());
+        assert_eq!(Some(Pos { x: 0, y: 1 }), min.s());
+        assert_eq!(Some(Pos { x: 1, y: 0 }), min.e());
     }
 
     proptest! {
+        #[test]
+        fn distances_are_symmetric(p1: Pos, p2: Pos) {
+            prop_assert_eq!(p1.chebyshev_distance(p2), p2.chebyshev_distance(p1));
+            prop_assert_eq!(p1.manhattan_distance(p2), p2.manhattan_distance(p1));
+            prop_assert_eq!(p1.euclidean_distance(p2), p2.euclidean_distance(p1));
+            prop_assert_eq!(0, p1.chebyshev_distance(p1));
+            prop_assert_eq!(0, p1.manhattan_distance(p1));
+        }
+
+        #[test]
+        fn distances_are_ordered(p1: Pos, p2: Pos) {
+            let chebyshev = f64::from(p1.chebyshev_distance(p2));
+            let euclidean = p1.euclidean_distance(p2);
+            let manhattan = f64::from(p1.manhattan_distance(p2));
+            prop_assert!(chebyshev <= euclidean);
+            prop_assert!(euclidean <= manhattan);
+        }
+
+        #[test]
+        fn distances_hold_triangle_inequality(p1: Pos, p2: Pos, p3: Pos) {
+            prop_assert!(
+                p1.chebyshev_distance(p3) <= p1.chebyshev_distance(p2) + p2.chebyshev_distance(p3)
+            );
+            prop_assert!(
+                p1.manhattan_distance(p3) <= p1.manhattan_distance(p2) + p2.manhattan_distance(p3)
+            );
+        }
+
+        #[test]
+        fn ord_agrees_with_eq(p1: Pos, p2: Pos) {
+            prop_assert_eq!(p1 == p2, p1.cmp(&p2) == Ordering::Equal);
+            prop_assert_eq!(p1.cmp(&p2), p2.cmp(&p1).reverse());
+        }
+
+        #[test]
+        fn steps_are_reversible(p in nonzero_pos_in_dim(MAX_DIM)) {
+            prop_assert_eq!(Some(p), p.n().and_then(Pos::s));
+            prop_assert_eq!(Some(p), p.s().and_then(Pos::n));
+            prop_assert_eq!(Some(p), p.w().and_then(Pos::e));
+            prop_assert_eq!(Some(p), p.e().and_then(Pos::w));
+        }
+
         #[test]
         fn pos_range_inclusive_unique_only(from: Pos, to: Pos) {
             let range = from..=to;
@@ -0,0 +1,320 @@
+use crate::data::structures::pos::PosCollection;
+use crate::data::structures::shapes::Shape;
+use crate::data::structures::{Dim, DimIndex, Pos};
+
+/// Axis-aligned rectangle covering positions from `from` (inclusive) up to `to` (exclusive)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rect {
+    pub from: Pos,
+    pub to: Pos,
+}
+
+impl Rect {
+    /// Half-open rectangle between two corners given in any order
+    pub fn new(p1: Pos, p2: Pos) -> Self {
+        let from = Pos {
+            x: DimIndex::min(p1.x, p2.x),
+            y: DimIndex::min(p1.y, p2.y),
+        };
+        let to = Pos {
+            x: DimIndex::max(p1.x, p2.x),
+            y: DimIndex::max(p1.y, p2.y),
+        };
+        Rect { from, to }
+    }
+
+    /// Rectangle including both corners, given in any order
+    pub fn inclusive(p1: Pos, p2: Pos) -> Self {
+        let r = Self::new(p1, p2);
+        let to = Pos {
+            x: r.to.x.checked_add(1).expect("Rect::inclusive x overflow"),
+            y: r.to.y.checked_add(1).expect("Rect::inclusive y overflow"),
+        };
+        Rect { to, ..r }
+    }
+
+    pub fn with_dim(from: Pos, dim: Dim) -> Self {
+        let to = Pos {
+            x: from.x + dim.width,
+            y: from.y + dim.height,
+        };
+        Rect { from, to }
+    }
+
+    pub fn width(&self) -> DimIndex {
+        self.to.x - self.from.x
+    }
+
+    pub fn height(&self) -> DimIndex {
+        self.to.y - self.from.y
+    }
+
+    pub fn dim(&self) -> Dim {
+        Dim {
+            width: self.width(),
+            height: self.height(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width() == 0 || self.height() == 0
+    }
+
+    /// Last position inside of the rectangle
+    pub fn max_pos(&self) -> Pos {
+        assert!(!self.is_empty());
+        Pos {
+            x: self.to.x - 1,
+            y: self.to.y - 1,
+        }
+    }
+
+    pub fn center(&self) -> Pos {
+        Pos {
+            x: self.from.x + self.width() / 2,
+            y: self.from.y + self.height() / 2,
+        }
+    }
+
+    pub fn contains(&self, p: Pos) -> bool {
+        self.from.x <= p.x && p.x < self.to.x && self.from.y <= p.y && p.y < self.to.y
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.overlap(other).is_some()
+    }
+
+    pub fn overlap(&self, other: &Rect) -> Option<Rect> {
+        let r = Rect {
+            from: Pos {
+                x: DimIndex::max(self.from.x, other.from.x),
+                y: DimIndex::max(self.from.y, other.from.y),
+            },
+            to: Pos {
+                x: DimIndex::min(self.to.x, other.to.x),
+                y: DimIndex::min(self.to.y, other.to.y),
+            },
+        };
+        if r.from.x < r.to.x && r.from.y < r.to.y {
+            Some(r)
+        } else {
+            None
+        }
+    }
+
+    /// Grows every side by `by`, saturating at the coordinate limits
+    pub fn inflate(&self, by: DimIndex) -> Rect {
+        Rect {
+            from: Pos {
+                x: self.from.x.saturating_sub(by),
+                y: self.from.y.saturating_sub(by),
+            },
+            to: Pos {
+                x: self.to.x.saturating_add(by),
+                y: self.to.y.saturating_add(by),
+            },
+        }
+    }
+
+    /// Shrinks every side by `by`, collapsing into an empty rectangle at the center
+    pub fn deflate(&self, by: DimIndex) -> Rect {
+        let shrink = |from: DimIndex, to: DimIndex| {
+            if to - from > by.saturating_mul(2) {
+                (from + by, to - by)
+            } else {
+                let mid = from + (to - from) / 2;
+                (mid, mid)
+            }
+        };
+        let (from_x, to_x) = shrink(self.from.x, self.to.x);
+        let (from_y, to_y) = shrink(self.from.y, self.to.y);
+        Rect {
+            from: Pos {
+                x: from_x,
+                y: from_y,
+            },
+            to: Pos { x: to_x, y: to_y },
+        }
+    }
+
+    /// Splits into columns `[from.x, from.x + at)` and `[from.x + at, to.x)`
+    pub fn split_x(&self, at: DimIndex) -> (Rect, Rect) {
+        assert!(at <= self.width());
+        let x = self.from.x + at;
+        let left = Rect {
+            to: Pos { x, y: self.to.y },
+            ..*self
+        };
+        let right = Rect {
+            from: Pos { x, y: self.from.y },
+            ..*self
+        };
+        (left, right)
+    }
+
+    /// Splits into rows `[from.y, from.y + at)` and `[from.y + at, to.y)`
+    pub fn split_y(&self, at: DimIndex) -> (Rect, Rect) {
+        assert!(at <= self.height());
+        let y = self.from.y + at;
+        let top = Rect {
+            to: Pos { x: self.to.x, y },
+            ..*self
+        };
+        let bottom = Rect {
+            from: Pos { x: self.from.x, y },
+            ..*self
+        };
+        (top, bottom)
+    }
+
+    pub fn iter(&self) -> RectIter {
+        RectIter {
+            rect: *self,
+            next: if self.is_empty() {
+                None
+            } else {
+                Some(self.from)
+            },
+        }
+    }
+}
+
+impl PosCollection for Rect {
+    type Iter = RectIter;
+
+    fn iter_pos(&self) -> RectIter {
+        self.iter()
+    }
+}
+
+impl Shape for Rect {
+    fn contains(&self, p: Pos) -> bool {
+        Rect::contains(self, p)
+    }
+}
+
+/// Column-major iterator over a `Rect`
+#[derive(Debug, Clone)]
+pub struct RectIter {
+    rect: Rect,
+    next: Option<Pos>,
+}
+
+impl Iterator for RectIter {
+    type Item = Pos;
+
+    fn next(&mut self) -> Option<Pos> {
+        let current = self.next?;
+        self.next = if current.y + 1 < self.rect.to.y {
+            Some(Pos {
+                y: current.y + 1,
+                ..current
+            })
+        } else if current.x + 1 < self.rect.to.x {
+            Some(Pos {
+                x: current.x + 1,
+                y: self.rect.from.y,
+            })
+        } else {
+            None
+        };
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::collections::HashSet;
+
+    use proptest::prelude::*;
+    use proptest::{prop_assert, prop_assert_eq, proptest, proptest_helper};
+
+    use super::*;
+    use crate::data::structures::pos::test::*;
+
+    pub fn rect_in(d: Dim) -> BoxedStrategy<Rect> {
+        (pos_in_dim(d), pos_in_dim(d))
+            .prop_map(|(p1, p2)| Rect::new(p1, p2))
+            .boxed()
+    }
+
+    impl Arbitrary for Rect {
+        type Parameters = ();
+
+        fn arbitrary_with(_args: <Self as Arbitrary>::Parameters) -> <Self as Arbitrary>::Strategy {
+            rect_in(SMALL_DIM)
+        }
+
+        type Strategy = BoxedStrategy<Rect>;
+    }
+
+    #[test]
+    fn inclusive_contains_corners() {
+        let r = Rect::inclusive(Pos { x: 3, y: 4 }, Pos { x: 1, y: 1 });
+        assert!(r.contains(Pos { x: 1, y: 1 }));
+        assert!(r.contains(Pos { x: 3, y: 4 }));
+        assert_eq!(Pos { x: 3, y: 4 }, r.max_pos());
+        assert_eq!(12, r.iter().count());
+    }
+
+    proptest! {
+        #[test]
+        fn iter_matches_contains(r: Rect) {
+            let all = r.iter().collect::<HashSet<_>>();
+            prop_assert_eq!(r.width() as usize * r.height() as usize, all.len());
+            prop_assert_eq!(all.len(), r.iter().count());
+            for p in r.inflate(1).iter() {
+                prop_assert_eq!(all.contains(&p), r.contains(p));
+            }
+        }
+
+        #[test]
+        fn center_is_inside(r: Rect) {
+            prop_assert_eq!(!r.is_empty(), r.contains(r.center()));
+        }
+
+        #[test]
+        fn intersects_when_sharing_positions(r1: Rect, r2: Rect) {
+            let shared = r1.iter().any(|p| r2.contains(p));
+            prop_assert_eq!(shared, r1.intersects(&r2));
+            prop_assert_eq!(r1.intersects(&r2), r2.intersects(&r1));
+            if let Some(i) = r1.overlap(&r2) {
+                for p in i.iter() {
+                    prop_assert!(r1.contains(p) && r2.contains(p));
+                }
+            }
+        }
+
+        #[test]
+        fn inflate_and_deflate(r: Rect, by in 0..10 as DimIndex) {
+            let inflated = r.inflate(by);
+            for p in r.iter() {
+                prop_assert!(inflated.contains(p));
+            }
+            let deflated = r.deflate(by);
+            for p in deflated.iter() {
+                prop_assert!(r.contains(p));
+            }
+            if r.from.x >= by && r.from.y >= by {
+                prop_assert_eq!(r, inflated.deflate(by));
+            }
+        }
+
+        #[test]
+        fn split_partitions(r: Rect, at in 0..100 as DimIndex) {
+            let at_x = DimIndex::min(at, r.width());
+            let (left, right) = r.split_x(at_x);
+            prop_assert_eq!(r.width(), left.width() + right.width());
+            prop_assert!(!left.intersects(&right));
+
+            let at_y = DimIndex::min(at, r.height());
+            let (top, bottom) = r.split_y(at_y);
+            prop_assert_eq!(r.height(), top.height() + bottom.height());
+            prop_assert!(!top.intersects(&bottom));
+            for p in r.iter() {
+                prop_assert!(left.contains(p) != right.contains(p));
+                prop_assert!(top.contains(p) != bottom.contains(p));
+            }
+        }
+    }
+}
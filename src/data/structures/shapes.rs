@@ -0,0 +1,379 @@
+use std::ops::RangeInclusive;
+
+use crate::data::structures::pos::PosCollection;
+use crate::data::structures::{DimIndex, Pos, Rect, Room};
+
+type ShapeIter = <Vec<Pos> as IntoIterator>::IntoIter;
+
+/// A set of positions which can also answer membership queries
+pub trait Shape: PosCollection {
+    fn contains(&self, p: Pos) -> bool;
+
+    fn union<S: Shape>(self, other: S) -> Union<Self, S>
+    where
+        Self: Sized,
+    {
+        Union(self, other)
+    }
+
+    fn intersection<S: Shape>(self, other: S) -> Intersection<Self, S>
+    where
+        Self: Sized,
+    {
+        Intersection(self, other)
+    }
+
+    fn difference<S: Shape>(self, other: S) -> Difference<Self, S>
+    where
+        Self: Sized,
+    {
+        Difference(self, other)
+    }
+}
+
+fn bounding_box(center: Pos, radius: DimIndex) -> Rect {
+    let from = Pos {
+        x: center.x.saturating_sub(radius),
+        y: center.y.saturating_sub(radius),
+    };
+    let to = Pos {
+        x: center.x.saturating_add(radius),
+        y: center.y.saturating_add(radius),
+    };
+    Rect::inclusive(from, to)
+}
+
+fn filter_box<S: Shape>(shape: &S, bbox: Rect) -> ShapeIter {
+    bbox.iter()
+        .filter(|p| shape.contains(*p))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub center: Pos,
+    pub radius: DimIndex,
+}
+
+impl Shape for Circle {
+    fn contains(&self, p: Pos) -> bool {
+        let d = self.center.euclidean_distance(p);
+        d <= f64::from(self.radius)
+    }
+}
+
+impl PosCollection for Circle {
+    type Iter = ShapeIter;
+
+    fn iter_pos(&self) -> ShapeIter {
+        filter_box(self, bounding_box(self.center, self.radius))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diamond {
+    pub center: Pos,
+    pub radius: DimIndex,
+}
+
+impl Shape for Diamond {
+    fn contains(&self, p: Pos) -> bool {
+        self.center.manhattan_distance(p) <= u32::from(self.radius)
+    }
+}
+
+impl PosCollection for Diamond {
+    type Iter = ShapeIter;
+
+    fn iter_pos(&self) -> ShapeIter {
+        filter_box(self, bounding_box(self.center, self.radius))
+    }
+}
+
+/// Outermost positions of a rectangle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Border {
+    pub rect: Rect,
+}
+
+impl Shape for Border {
+    fn contains(&self, p: Pos) -> bool {
+        if !self.rect.contains(p) {
+            return false;
+        }
+        let (from, to) = (self.rect.from, self.rect.max_pos());
+        p.x == from.x || p.x == to.x || p.y == from.y || p.y == to.y
+    }
+}
+
+impl PosCollection for Border {
+    type Iter = ShapeIter;
+
+    fn iter_pos(&self) -> ShapeIter {
+        filter_box(self, self.rect)
+    }
+}
+
+/// Closed polygon through `vertices`; edges count as inside
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub vertices: Vec<Pos>,
+}
+
+impl Polygon {
+    pub fn new(vertices: Vec<Pos>) -> Self {
+        assert!(!vertices.is_empty());
+        Polygon { vertices }
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Pos, Pos)> + '_ {
+        let next = self.vertices.iter().cycle().skip(1);
+        self.vertices.iter().cloned().zip(next.cloned())
+    }
+
+    fn on_edge(p: Pos, a: Pos, b: Pos) -> bool {
+        let (px, py) = (i64::from(p.x), i64::from(p.y));
+        let (ax, ay) = (i64::from(a.x), i64::from(a.y));
+        let (bx, by) = (i64::from(b.x), i64::from(b.y));
+        let cross = (bx - ax) * (py - ay) - (by - ay) * (px - ax);
+        cross == 0 && Rect::inclusive(a, b).contains(p)
+    }
+}
+
+impl Shape for Polygon {
+    fn contains(&self, p: Pos) -> bool {
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            if Self::on_edge(p, a, b) {
+                return true;
+            }
+            if (a.y > p.y) != (b.y > p.y) {
+                let (ax, ay) = (f64::from(a.x), f64::from(a.y));
+                let (bx, by) = (f64::from(b.x), f64::from(b.y));
+                let cross_x = ax + (bx - ax) * (f64::from(p.y) - ay) / (by - ay);
+                if f64::from(p.x) < cross_x {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+}
+
+impl PosCollection for Polygon {
+    type Iter = ShapeIter;
+
+    fn iter_pos(&self) -> ShapeIter {
+        let first = self.vertices[0];
+        let (from, to) = self.vertices.iter().fold((first, first), |(from, to), v| {
+            let from = Pos {
+                x: DimIndex::min(from.x, v.x),
+                y: DimIndex::min(from.y, v.y),
+            };
+            let to = Pos {
+                x: DimIndex::max(to.x, v.x),
+                y: DimIndex::max(to.y, v.y),
+            };
+            (from, to)
+        });
+        filter_box(self, Rect::inclusive(from, to))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Union<A, B>(pub A, pub B);
+
+impl<A: Shape, B: Shape> Shape for Union<A, B> {
+    fn contains(&self, p: Pos) -> bool {
+        self.0.contains(p) || self.1.contains(p)
+    }
+}
+
+impl<A: Shape, B: Shape> PosCollection for Union<A, B> {
+    type Iter = ShapeIter;
+
+    fn iter_pos(&self) -> ShapeIter {
+        let mut all = self.0.iter_pos().collect::<Vec<_>>();
+        all.extend(self.1.iter_pos().filter(|p| !self.0.contains(*p)));
+        all.into_iter()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Intersection<A, B>(pub A, pub B);
+
+impl<A: Shape, B: Shape> Shape for Intersection<A, B> {
+    fn contains(&self, p: Pos) -> bool {
+        self.0.contains(p) && self.1.contains(p)
+    }
+}
+
+impl<A: Shape, B: Shape> PosCollection for Intersection<A, B> {
+    type Iter = ShapeIter;
+
+    fn iter_pos(&self) -> ShapeIter {
+        self.0
+            .iter_pos()
+            .filter(|p| self.1.contains(*p))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference<A, B>(pub A, pub B);
+
+impl<A: Shape, B: Shape> Shape for Difference<A, B> {
+    fn contains(&self, p: Pos) -> bool {
+        self.0.contains(p) && !self.1.contains(p)
+    }
+}
+
+impl<A: Shape, B: Shape> PosCollection for Difference<A, B> {
+    type Iter = ShapeIter;
+
+    fn iter_pos(&self) -> ShapeIter {
+        self.0
+            .iter_pos()
+            .filter(|p| !self.1.contains(*p))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+impl Shape for RangeInclusive<Pos> {
+    fn contains(&self, p: Pos) -> bool {
+        let (s, e) = (self.start(), self.end());
+        DimIndex::min(s.x, e.x) <= p.x
+            && DimIndex::max(s.x, e.x) >= p.x
+            && DimIndex::min(s.y, e.y) <= p.y
+            && DimIndex::max(s.y, e.y) >= p.y
+    }
+}
+
+impl Shape for Room {
+    fn contains(&self, p: Pos) -> bool {
+        Room::contains(self, p)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use itertools::Itertools;
+    use proptest::prelude::*;
+    use proptest::{prop_assert, prop_assert_eq, proptest, proptest_helper};
+
+    use super::*;
+    use crate::data::structures::pos::test::*;
+
+    fn center_and_radius() -> BoxedStrategy<(Pos, DimIndex)> {
+        (pos_in_dim(SMALL_DIM), 0..20 as DimIndex).boxed()
+    }
+
+    fn circle() -> BoxedStrategy<Circle> {
+        center_and_radius()
+            .prop_map(|(center, radius)| Circle { center, radius })
+            .boxed()
+    }
+
+    fn diamond() -> BoxedStrategy<Diamond> {
+        center_and_radius()
+            .prop_map(|(center, radius)| Diamond { center, radius })
+            .boxed()
+    }
+
+    fn assert_consistent<S: Shape>(s: &S, bbox: Rect) -> Result<(), TestCaseError> {
+        let positions = s.iter_pos().collect::<HashSet<_>>();
+        prop_assert_eq!(positions.len(), s.iter_pos().count());
+        for p in bbox.iter() {
+            prop_assert_eq!(s.contains(p), positions.contains(&p), "{:?}", p);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn diamond_size() {
+        let center = Pos { x: 10, y: 10 };
+        for radius in 0..5 {
+            let d = Diamond { center, radius };
+            let r = radius as usize;
+            assert_eq!(2 * r * r + 2 * r + 1, d.iter_pos().count());
+        }
+    }
+
+    #[test]
+    fn circle_of_zero_radius_is_its_center() {
+        let center = Pos { x: 0, y: 3 };
+        let c = Circle { center, radius: 0 };
+        assert_eq!(vec![center], c.iter_pos().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn border_is_hollow() {
+        let border = Border {
+            rect: Rect::inclusive(Pos { x: 1, y: 1 }, Pos { x: 4, y: 3 }),
+        };
+        assert_eq!(10, border.iter_pos().count());
+        assert!(!border.contains(Pos { x: 2, y: 2 }));
+        assert!(border.contains(Pos { x: 4, y: 2 }));
+    }
+
+    #[test]
+    fn triangle_polygon() {
+        let triangle = Polygon::new(vec![
+            Pos { x: 0, y: 0 },
+            Pos { x: 4, y: 0 },
+            Pos { x: 0, y: 4 },
+        ]);
+        assert_eq!(15, triangle.iter_pos().count());
+        assert!(triangle.contains(Pos { x: 2, y: 2 }));
+        assert!(!triangle.contains(Pos { x: 3, y: 2 }));
+    }
+
+    proptest! {
+        #[test]
+        fn circle_is_consistent(c in circle()) {
+            assert_consistent(&c, bounding_box(c.center, c.radius + 1))?;
+            prop_assert!(c.contains(c.center));
+        }
+
+        #[test]
+        fn diamond_is_consistent(d in diamond()) {
+            assert_consistent(&d, bounding_box(d.center, d.radius + 1))?;
+            let circle = Circle { center: d.center, radius: d.radius };
+            prop_assert!(circle.iter_pos().count() >= d.iter_pos().count());
+        }
+
+        #[test]
+        fn rectangle_polygon_is_range(from in pos_in_dim(SMALL_DIM), to in pos_in_dim(SMALL_DIM)) {
+            let corners = vec![from, Pos { x: to.x, y: from.y }, to, Pos { x: from.x, y: to.y }];
+            let polygon = Polygon::new(corners);
+            let expected = (from..=to).iter_pos().sorted_by_key(|p| (p.x, p.y));
+            let actual = polygon.iter_pos().sorted_by_key(|p| (p.x, p.y));
+            prop_assert!(expected.eq(actual));
+        }
+
+        #[test]
+        fn set_operations(c in circle(), d in diamond()) {
+            let cs = c.iter_pos().collect::<HashSet<_>>();
+            let ds = d.iter_pos().collect::<HashSet<_>>();
+            let radius = DimIndex::max(c.radius, d.radius) + c.center.chebyshev_distance(d.center);
+            let bbox = bounding_box(c.center, radius);
+
+            let union = c.union(d);
+            assert_consistent(&union, bbox)?;
+            prop_assert_eq!(&cs | &ds, union.iter_pos().collect::<HashSet<_>>());
+
+            let intersection = c.intersection(d);
+            assert_consistent(&intersection, bbox)?;
+            prop_assert_eq!(&cs & &ds, intersection.iter_pos().collect::<HashSet<_>>());
+
+            let difference = c.difference(d);
+            assert_consistent(&difference, bbox)?;
+            prop_assert_eq!(&cs - &ds, difference.iter_pos().collect::<HashSet<_>>());
+        }
+    }
+}
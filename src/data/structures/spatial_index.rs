@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use specs::world::Index;
+use specs::{Component, Entity, ReadStorage};
+
+use crate::data::structures::matrix::Matrix;
+use crate::data::structures::{Dim, DimIndex, HasDim, Pos, Rect};
+
+/// Occupancy of level cells, answers "who is at this position" without joining over all entities
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    cells: Matrix<Vec<Entity>>,
+    located: HashMap<Index, Pos>,
+}
+
+impl SpatialIndex {
+    pub fn with_dim(dim: Dim) -> Self {
+        SpatialIndex {
+            cells: Matrix::new(dim.width, dim.height),
+            located: HashMap::new(),
+        }
+    }
+
+    /// Drops all entries, resizing to `dim` when level dimensions changed
+    pub fn reset(&mut self, dim: Dim) {
+        if self.cells.width() != dim.width || self.cells.height() != dim.height {
+            *self = SpatialIndex::with_dim(dim);
+        } else {
+            let dim = self.cells.dim();
+            self.cells
+                .view_mut(Pos::default(), dim)
+                .iter_mut()
+                .for_each(|(_, entities)| entities.clear());
+            self.located.clear();
+        }
+    }
+
+    /// Puts `e` at `pos`, moving it when it was indexed somewhere else
+    pub fn insert(&mut self, pos: Pos, e: Entity) {
+        self.remove(e.id());
+        if self.cells.is_valid(pos) {
+            self.cells[pos].push(e);
+            self.located.insert(e.id(), pos);
+        } else {
+            log::warn!("Entity {:?} is outside of level at {:?}", e, pos);
+        }
+    }
+
+    /// Drops the entity with index `id`, it doesn't have to be alive anymore
+    pub fn remove(&mut self, id: Index) {
+        if let Some(pos) = self.located.remove(&id) {
+            self.cells[pos].retain(|e| e.id() != id);
+        }
+    }
+
+    pub fn entities_at(&self, pos: Pos) -> &[Entity] {
+        if self.cells.is_valid(pos) {
+            &self.cells[pos]
+        } else {
+            &[]
+        }
+    }
+
+    /// First entity at `pos` which has a blocking component
+    pub fn blocking_at<T: Component>(&self, pos: Pos, blockers: &ReadStorage<T>) -> Option<Entity> {
+        self.entities_at(pos)
+            .iter()
+            .cloned()
+            .find(|&e| blockers.get(e).is_some())
+    }
+
+    /// Entities within chebyshev distance `radius` from `center`
+    pub fn within_radius(
+        &self,
+        center: Pos,
+        radius: DimIndex,
+    ) -> impl Iterator<Item = (Pos, Entity)> + '_ {
+        let bounds = Rect::with_dim(Pos::default(), self.cells.dim());
+        let area = Rect::inclusive(center, center)
+            .inflate(radius)
+            .overlap(&bounds)
+            .unwrap_or_default();
+        area.iter()
+            .flat_map(move |p| self.entities_at(p).iter().map(move |&e| (p, e)))
+    }
+}
+
+impl HasDim for SpatialIndex {
+    fn width(&self) -> DimIndex {
+        self.cells.width()
+    }
+
+    fn height(&self) -> DimIndex {
+        self.cells.height()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use specs::prelude::*;
+    use specs_derive::*;
+
+    use super::*;
+
+    #[derive(Component, Debug, Default)]
+    struct Blocker;
+
+    const DIM: Dim = Dim {
+        width: 5,
+        height: 5,
+    };
+
+    #[test]
+    fn finds_entities_at_position() {
+        let mut w = World::new();
+        let e1 = w.create_entity().build();
+        let e2 = w.create_entity().build();
+        let mut index = SpatialIndex::with_dim(DIM);
+        let pos = Pos { x: 1, y: 2 };
+        index.insert(pos, e1);
+        index.insert(pos, e2);
+
+        assert_eq!(index.entities_at(pos), &[e1, e2]);
+        assert!(index.entities_at(Pos { x: 2, y: 1 }).is_empty());
+        assert!(index.entities_at(Pos { x: 10, y: 10 }).is_empty());
+
+        index.reset(DIM);
+        assert!(index.entities_at(pos).is_empty());
+    }
+
+    #[test]
+    fn insert_moves_indexed_entities() {
+        let mut w = World::new();
+        let e = w.create_entity().build();
+        let mut index = SpatialIndex::with_dim(DIM);
+        index.insert(Pos { x: 1, y: 1 }, e);
+        index.insert(Pos { x: 2, y: 1 }, e);
+        assert!(index.entities_at(Pos { x: 1, y: 1 }).is_empty());
+        assert_eq!(index.entities_at(Pos { x: 2, y: 1 }), &[e]);
+
+        index.remove(e.id());
+        assert!(index.entities_at(Pos { x: 2, y: 1 }).is_empty());
+    }
+
+    #[test]
+    fn blocking_at_skips_non_blockers() {
+        let mut w = World::new();
+        w.register::<Blocker>();
+        let item = w.create_entity().build();
+        let monster = w.create_entity().with(Blocker).build();
+        let mut index = SpatialIndex::with_dim(DIM);
+        let pos = Pos { x: 0, y: 0 };
+        index.insert(pos, item);
+
+        let blockers = w.read_storage::<Blocker>();
+        assert_eq!(index.blocking_at(pos, &blockers), None);
+        index.insert(pos, monster);
+        assert_eq!(index.blocking_at(pos, &blockers), Some(monster));
+    }
+
+    #[test]
+    fn within_radius_uses_chebyshev_distance() {
+        let mut w = World::new();
+        let near = w.create_entity().build();
+        let corner = w.create_entity().build();
+        let far = w.create_entity().build();
+        let mut index = SpatialIndex::with_dim(DIM);
+        index.insert(Pos { x: 1, y: 1 }, near);
+        index.insert(Pos { x: 0, y: 0 }, corner);
+        index.insert(Pos { x: 4, y: 4 }, far);
+
+        let mut found: Vec<_> = index
+            .within_radius(Pos { x: 1, y: 1 }, 1)
+            .map(|(_, e)| e)
+            .collect();
+        found.sort();
+        let mut expected = vec![near, corner];
+        expected.sort();
+        assert_eq!(found, expected);
+        assert_eq!(index.within_radius(Pos { x: 4, y: 4 }, 0).count(), 1);
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Index;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::data::structures::{CellObject, Cost};
+use crate::systems::render::Color;
+
+const BUILTIN_SOURCE: &str = include_str!("../../../static/tiles.toml");
+
+/// Compact reference to a tile definition of a `TileRegistry`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TileId(u8);
+
+impl TileId {
+    pub const WALL: TileId = TileId(0);
+    pub const GROUND: TileId = TileId(1);
+    pub const ROOM_WALL: TileId = TileId(2);
+    pub const OPEN_DOOR: TileId = TileId(3);
+    pub const CLOSED_DOOR: TileId = TileId(4);
+    pub const LOCKED_DOOR: TileId = TileId(5);
+    pub const STAIRS_UP: TileId = TileId(6);
+    pub const STAIRS_DOWN: TileId = TileId(7);
+    pub const SHALLOW_WATER: TileId = TileId(8);
+    pub const DEEP_WATER: TileId = TileId(9);
+    pub const LAVA: TileId = TileId(10);
+    pub const RUBBLE: TileId = TileId(11);
+
+    pub fn index(self) -> usize {
+        self.0.into()
+    }
+}
+
+/// Tiles the game places by itself with their names, every registry has to define them
+/// under these ids
+const BUILTIN_TILES: [(TileId, &str); 12] = [
+    (TileId::WALL, "wall"),
+    (TileId::GROUND, "ground"),
+    (TileId::ROOM_WALL, "room_wall"),
+    (TileId::OPEN_DOOR, "open_door"),
+    (TileId::CLOSED_DOOR, "closed_door"),
+    (TileId::LOCKED_DOOR, "locked_door"),
+    (TileId::STAIRS_UP, "stairs_up"),
+    (TileId::STAIRS_DOWN, "stairs_down"),
+    (TileId::SHALLOW_WATER, "shallow_water"),
+    (TileId::DEEP_WATER, "deep_water"),
+    (TileId::LAVA, "lava"),
+    (TileId::RUBBLE, "rubble"),
+];
+
+/// One `[[tile]]` entry of a definition file, see `static/tiles.toml` for its keys
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileDef {
+    pub name: String,
+    pub glyph: char,
+    #[serde(deserialize_with = "color")]
+    pub fg: Color,
+    #[serde(default, deserialize_with = "optional_color")]
+    pub bg: Option<Color>,
+    #[serde(default = "yes")]
+    pub walkable: bool,
+    #[serde(default = "yes")]
+    pub transparent: bool,
+    #[serde(default = "single_cost")]
+    pub cost: Cost,
+    #[serde(default)]
+    pub damage: i32,
+    /// Given by name in the file, looked up once all tiles are known
+    #[serde(skip)]
+    pub opens_to: Option<TileId>,
+}
+
+impl CellObject for TileDef {
+    fn is_walkable(&self) -> bool {
+        self.walkable
+    }
+
+    fn blocks_sight(&self) -> bool {
+        !self.transparent
+    }
+
+    fn movement_cost(&self) -> Cost {
+        self.cost
+    }
+
+    fn damage_on_enter(&self) -> i32 {
+        self.damage
+    }
+
+    fn is_openable(&self) -> bool {
+        self.opens_to.is_some()
+    }
+}
+
+#[derive(Debug)]
+pub enum TileRegistryError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// Definition number `index` (counting from 0) is wrong
+    Tile {
+        index: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for TileRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TileRegistryError::Io(e) => write!(f, "can't read tiles: {}", e),
+            TileRegistryError::Parse(e) => write!(f, "can't parse tiles: {}", e),
+            TileRegistryError::Tile { index, message } => write!(f, "tile {}: {}", index, message),
+        }
+    }
+}
+
+impl Error for TileRegistryError {}
+
+impl From<io::Error> for TileRegistryError {
+    fn from(e: io::Error) -> Self {
+        TileRegistryError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for TileRegistryError {
+    fn from(e: toml::de::Error) -> Self {
+        TileRegistryError::Parse(e)
+    }
+}
+
+/// All kinds of terrain known to the game, read from `[[tile]]` entries of a definition file
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileRegistry {
+    tiles: Vec<TileDef>,
+    by_name: HashMap<String, TileId>,
+}
+
+thread_local! {
+    static BUILTIN: Arc<TileRegistry> = Arc::new(
+        TileRegistry::parse(BUILTIN_SOURCE).expect("static/tiles.toml is broken")
+    );
+}
+
+impl TileRegistry {
+    /// Tiles of `static/tiles.toml` as it was at build time
+    pub fn builtin() -> Arc<TileRegistry> {
+        BUILTIN.with(Arc::clone)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, TileRegistryError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Arc<TileRegistry>, TileRegistryError> {
+        Self::from_file(path).map(Arc::new)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, TileRegistryError> {
+        let file: TileFile = toml::from_str(source)?;
+        if file.tile.len() > usize::from(u8::max_value()) + 1 {
+            return Err(TileRegistryError::Tile {
+                index: file.tile.len() - 1,
+                message: "too many tiles".to_owned(),
+            });
+        }
+
+        let mut tiles = vec![];
+        let mut opens_to = vec![];
+        let mut by_name = HashMap::new();
+        for (index, mut entry) in file.tile.into_iter().enumerate() {
+            let error = |message: String| TileRegistryError::Tile { index, message };
+            opens_to.push(match entry.remove("opens_to") {
+                None => None,
+                Some(toml::Value::String(other)) => Some(other),
+                Some(_) => return Err(error("opens_to must be a string".to_owned())),
+            });
+            let tile: TileDef = toml::Value::Table(entry)
+                .try_into()
+                .map_err(|e| error(e.to_string()))?;
+            if tile.cost < 1 {
+                return Err(error(format!("cost {} must be positive", tile.cost)));
+            }
+            if tile.damage < 0 {
+                return Err(error(format!(
+                    "damage {} must not be negative",
+                    tile.damage
+                )));
+            }
+            if by_name
+                .insert(tile.name.clone(), TileId(index as u8))
+                .is_some()
+            {
+                return Err(error(format!("{} is defined twice", tile.name)));
+            }
+            tiles.push(tile);
+        }
+        for (id, name) in BUILTIN_TILES.iter() {
+            if by_name.get(*name) != Some(id) {
+                return Err(TileRegistryError::Tile {
+                    index: id.index(),
+                    message: format!("expected builtin tile {}", name),
+                });
+            }
+        }
+        for (index, (tile, other)) in tiles.iter_mut().zip(opens_to).enumerate() {
+            if let Some(other) = other {
+                tile.opens_to =
+                    Some(*by_name.get(&other).ok_or_else(|| TileRegistryError::Tile {
+                        index,
+                        message: format!("opens to unknown tile {}", other),
+                    })?);
+            }
+        }
+        Ok(TileRegistry { tiles, by_name })
+    }
+
+    pub fn id(&self, name: &str) -> Option<TileId> {
+        self.by_name.get(name).cloned()
+    }
+
+    pub fn get(&self, id: TileId) -> Option<&TileDef> {
+        self.tiles.get(id.index())
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (TileId, &TileDef)> {
+        self.tiles
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (TileId(i as u8), t))
+    }
+}
+
+impl Index<TileId> for TileRegistry {
+    type Output = TileDef;
+
+    fn index(&self, id: TileId) -> &TileDef {
+        &self.tiles[id.index()]
+    }
+}
+
+/// Layout of a definition file
+#[derive(Deserialize)]
+struct TileFile {
+    #[serde(default)]
+    tile: Vec<toml::value::Table>,
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn single_cost() -> Cost {
+    1
+}
+
+/// Colour written as `[r, g, b]`
+fn color<'de, D: Deserializer<'de>>(d: D) -> Result<Color, D::Error> {
+    let [r, g, b] = <[u8; 3]>::deserialize(d)?;
+    Ok(Color::new(r, g, b))
+}
+
+fn optional_color<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Color>, D::Error> {
+    color(d).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXTRA: &str = r#"
+        [[tile]]
+        name = "grass"
+        glyph = "\""
+        fg = [0, 255, 0]
+        cost = 4
+    "#;
+
+    #[test]
+    fn builtin_tiles_match_ids() {
+        let tiles = TileRegistry::builtin();
+        for (id, name) in BUILTIN_TILES.iter() {
+            assert_eq!(tiles.id(name), Some(*id));
+            assert_eq!(tiles[*id].name, *name);
+        }
+        assert_eq!(tiles[TileId::RUBBLE].name, "rubble");
+        assert_eq!(tiles[TileId::CLOSED_DOOR].opens_to, Some(TileId::OPEN_DOOR));
+        assert!(tiles[TileId::CLOSED_DOOR].is_openable());
+        assert!(!tiles[TileId::LOCKED_DOOR].is_openable());
+        assert!(!tiles[TileId::LOCKED_DOOR].is_walkable());
+        assert!(tiles[TileId::WALL].blocks_sight());
+        assert!(!tiles[TileId::DEEP_WATER].blocks_sight());
+        assert_eq!(tiles[TileId::LAVA].damage_on_enter(), 10);
+        assert_eq!(tiles[TileId::GROUND].movement_cost(), 1);
+    }
+
+    #[test]
+    fn new_tiles_go_after_builtin() {
+        let tiles = TileRegistry::parse(&format!("{}{}", BUILTIN_SOURCE, EXTRA)).unwrap();
+        let grass = tiles.id("grass").unwrap();
+        assert_eq!(grass.index(), BUILTIN_TILES.len());
+        assert_eq!(tiles[grass].glyph, '"');
+        assert_eq!(tiles[grass].fg, Color::new(0, 255, 0));
+        assert_eq!(tiles[grass].bg, None);
+        assert!(tiles[grass].is_walkable());
+        assert_eq!(tiles[grass].movement_cost(), 4);
+        assert_eq!(tiles.iter().count(), tiles.len());
+    }
+
+    #[test]
+    fn broken_definitions_are_rejected() {
+        let wrong = |replace: &str, with: &str| {
+            let source = format!("{}{}", BUILTIN_SOURCE, EXTRA).replace(replace, with);
+            TileRegistry::parse(&source).unwrap_err()
+        };
+        let extra = BUILTIN_TILES.len();
+        let tile_error = |e: TileRegistryError| match e {
+            TileRegistryError::Tile { index, .. } => index,
+            e => panic!("{}", e),
+        };
+        assert_eq!(tile_error(wrong("[0, 255, 0]", "[0, 256, 0]")), extra);
+        assert_eq!(tile_error(wrong("fg = [0, 255, 0]", "")), extra);
+        assert_eq!(
+            tile_error(wrong("glyph = \"\\\"\"", "glyph = \"ab\"")),
+            extra
+        );
+        assert_eq!(
+            tile_error(wrong("name = \"grass\"", "name = \"wall\"")),
+            extra
+        );
+        assert_eq!(tile_error(wrong("cost = 4", "costs = 4")), extra);
+        assert_eq!(tile_error(wrong("\"open_door\"", "\"portal\"")), 3);
+        assert_eq!(tile_error(wrong("name = \"lava\"", "name = \"magma\"")), 10);
+        match wrong("fg = [0, 255, 0]", "fg = [0, 255, 0") {
+            TileRegistryError::Parse(e) => assert!(e.line_col().is_some()),
+            e => panic!("{}", e),
+        }
+    }
+}
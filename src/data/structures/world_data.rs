@@ -1,12 +1,15 @@
 use crate::data::components::IsFighter;
+use crate::data::structures::DimIndex;
 use crate::systems::render::Color;
 use crate::systems::render::Renderable;
 
 #[derive(Clone, Debug)]
 pub struct Race {
+    pub name: &'static str,
     pub default_char: char,
     pub default_color: Color,
     pub default_fight_skill: IsFighter,
+    pub sight_radius: DimIndex,
 }
 impl Race {
     pub fn to_template(&self) -> MonsterTemplate {
@@ -32,6 +35,8 @@ pub struct MonsterTemplate {
 
 const DEFAULT_COLOR: Color = tcod::colors::YELLOW;
 const DEFAULT_CHAR: char = '?';
+const DEFAULT_SIGHT_RADIUS: DimIndex = 6;
+const DEFAULT_NAME: &str = "monster";
 
 impl Renderable for MonsterTemplate {
     fn color(&self) -> Color {
@@ -49,4 +54,32 @@ impl MonsterTemplate {
     pub fn fight_skills(&self) -> &IsFighter {
         &self.race.as_ref().unwrap().default_fight_skill
     }
+
+    pub fn name(&self) -> &'static str {
+        self.race.as_ref().map_or(DEFAULT_NAME, |r| r.name)
+    }
+
+    pub fn sight_radius(&self) -> DimIndex {
+        self.race
+            .as_ref()
+            .map_or(DEFAULT_SIGHT_RADIUS, |r| r.sight_radius)
+    }
+}
+
+/// Thing lying on the floor, walking over it is fine
+#[derive(Clone, Debug)]
+pub struct ItemTemplate {
+    pub name: &'static str,
+    pub display_char: char,
+    pub color: Color,
+}
+
+impl Renderable for ItemTemplate {
+    fn color(&self) -> Color {
+        self.color
+    }
+
+    fn display_char(&self) -> char {
+        self.display_char
+    }
 }
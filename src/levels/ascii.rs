@@ -0,0 +1,432 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use specs::prelude::*;
+
+use crate::data::components::*;
+use crate::data::structures::matrix::Matrix;
+use crate::data::structures::pos::PosCollection;
+use crate::data::structures::*;
+use crate::levels::items::all_items;
+use crate::levels::races::all_monsters;
+use crate::systems::render::Renderable;
+
+/// Name of the player in map legends
+pub const PLAYER: &str = "player";
+
+/// Something standing on the map, named as in the legend
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub name: String,
+    pub glyph: char,
+    pub pos: Pos,
+}
+
+/// Level as plain text: a legend of `<char> = <name>` lines followed by rows of the map.
+/// Names are tile names of the registry, anything else is an entity standing on ground, or on
+/// another tile when written as `<name> on <tile>`:
+///
+/// ```text
+/// + = locked_door
+/// k = kobold on rubble
+///
+/// #####
+/// #@.k+
+/// #####
+/// ```
+///
+/// Without a legend entry a character stands for the first tile drawn with it, `@` for the
+/// player and monster characters for their race. Common indentation of lines is ignored, so
+/// maps can be written inline in tests
+#[derive(Debug, Clone)]
+pub struct AsciiMap {
+    pub level: LevelInfo,
+    pub placements: Vec<Placement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for MapError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Symbol {
+    Tile(TileId),
+    Entity { name: String, on: TileId },
+}
+
+impl AsciiMap {
+    pub fn new(level: LevelInfo) -> Self {
+        AsciiMap {
+            level,
+            placements: vec![],
+        }
+    }
+
+    /// Current level with the visible entities standing on it
+    pub fn from_world(world: &World) -> Self {
+        let (level, pos, vis, ispl, names): (
+            Read<LevelInfo>,
+            ReadStorage<HasPos>,
+            ReadStorage<IsVisible>,
+            ReadStorage<IsPlayer>,
+            ReadStorage<HasName>,
+        ) = world.system_data();
+        let mut placements: Vec<(bool, Placement)> = (&pos, &vis, ispl.maybe(), names.maybe())
+            .join()
+            .map(|(pos, vis, player, name)| {
+                let name = match (player, name) {
+                    (Some(_), _) => PLAYER,
+                    (None, Some(name)) => name.0,
+                    (None, None) => "monster",
+                };
+                let placement = Placement {
+                    name: name.to_owned(),
+                    glyph: vis.display_char,
+                    pos: pos.0,
+                };
+                (player.is_none(), placement)
+            })
+            .collect();
+        // Only one entity fits a tile, the player shouldn't be the one left out
+        placements.sort_by_key(|(not_player, _)| *not_player);
+        AsciiMap {
+            level: level.clone(),
+            placements: placements.into_iter().map(|(_, p)| p).collect(),
+        }
+    }
+
+    /// Map of the builtin tiles
+    pub fn parse(source: &str) -> Result<Self, MapError> {
+        Self::parse_with(source, TileRegistry::builtin())
+    }
+
+    pub fn parse_with(source: &str, tiles: Arc<TileRegistry>) -> Result<Self, MapError> {
+        let indent = source
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| l.len() - l.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l.get(indent..).unwrap_or("").trim_end()))
+            .filter(|(_, l)| !l.is_empty())
+            .peekable();
+
+        let mut legend = default_legend(&tiles);
+        while let Some((line, (c, value))) = lines
+            .peek()
+            .and_then(|&(line, l)| legend_entry(l).map(|e| (line, e)))
+        {
+            let symbol =
+                parse_symbol(value, &tiles).map_err(|message| MapError { line, message })?;
+            legend.insert(c, symbol);
+            lines.next();
+        }
+
+        let rows: Vec<(usize, Vec<char>)> = lines.map(|(i, l)| (i, l.chars().collect())).collect();
+        let error = |line: usize, message: String| MapError { line, message };
+        let width = rows
+            .first()
+            .map(|(_, r)| r.len())
+            .ok_or_else(|| error(source.lines().count(), "map has no rows".to_owned()))?;
+        if width > DimIndex::max_value().into() || rows.len() > DimIndex::max_value().into() {
+            return Err(error(rows[0].0, "map is too large".to_owned()));
+        }
+
+        let mut data = Vec::with_capacity(rows.len());
+        let mut placements = vec![];
+        for (y, (line, row)) in rows.iter().enumerate() {
+            if row.len() != width {
+                let message = format!("row is {} wide, expected {}", row.len(), width);
+                return Err(error(*line, message));
+            }
+            for (x, c) in row.iter().enumerate() {
+                let pos = Pos {
+                    x: x as DimIndex,
+                    y: y as DimIndex,
+                };
+                match legend.get(c) {
+                    Some(Symbol::Tile(id)) => data.push(*id),
+                    Some(Symbol::Entity { name, on }) => {
+                        data.push(*on);
+                        placements.push(Placement {
+                            name: name.clone(),
+                            glyph: *c,
+                            pos,
+                        });
+                    }
+                    None => return Err(error(*line, format!("{:?} is not in the legend", c))),
+                }
+            }
+        }
+        let dim = Dim {
+            width: width as DimIndex,
+            height: rows.len() as DimIndex,
+        };
+        let matrix = Matrix::tabulate(dim, |p| data[p.y as usize * width + p.x as usize]);
+        Ok(AsciiMap {
+            level: LevelInfo::with_tiles(tiles, matrix),
+            placements,
+        })
+    }
+
+    /// Positions of everything placed under `name`
+    pub fn find<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Pos> + 'a {
+        self.placements
+            .iter()
+            .filter(move |p| p.name == name)
+            .map(|p| p.pos)
+    }
+
+    pub fn player_pos(&self) -> Option<Pos> {
+        self.find(PLAYER).next()
+    }
+}
+
+/// Writes the map with a legend for every character used, so it reads back the same way with
+/// any defaults. When several entities share a tile only the first one is kept
+impl fmt::Display for AsciiMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = &self.level;
+        let mut placed: HashMap<Pos, &Placement> = HashMap::new();
+        for p in &self.placements {
+            placed.entry(p.pos).or_insert(p);
+        }
+
+        let mut used = HashSet::new();
+        let mut legend: Vec<(char, Symbol)> = vec![];
+        let mut grid: Matrix<char> = Matrix::new(level.width(), level.height());
+        for pos in level.iter_pos() {
+            let tile = level.tile(pos);
+            let (symbol, glyph) = match placed.get(&pos) {
+                Some(p) => (
+                    Symbol::Entity {
+                        name: p.name.clone(),
+                        on: tile,
+                    },
+                    p.glyph,
+                ),
+                None => (Symbol::Tile(tile), level[pos].glyph),
+            };
+            let existing = legend.iter().find(|(_, s)| *s == symbol).map(|(c, _)| *c);
+            let c = match existing {
+                Some(c) => c,
+                None => {
+                    let c = Some(glyph)
+                        .filter(|g| !used.contains(g) && !g.is_whitespace())
+                        .or_else(|| ('!'..='~').find(|c| !used.contains(c)))
+                        .ok_or(fmt::Error)?;
+                    used.insert(c);
+                    legend.push((c, symbol));
+                    c
+                }
+            };
+            grid[pos] = c;
+        }
+
+        let tiles = level.tiles();
+        for (c, symbol) in &legend {
+            match symbol {
+                Symbol::Tile(id) => writeln!(f, "{} = {}", c, tiles[*id].name)?,
+                Symbol::Entity { name, on } if *on == TileId::GROUND => {
+                    writeln!(f, "{} = {}", c, name)?
+                }
+                Symbol::Entity { name, on } => {
+                    writeln!(f, "{} = {} on {}", c, name, tiles[*on].name)?
+                }
+            }
+        }
+        for y in 0..level.height() {
+            writeln!(f)?;
+            for x in 0..level.width() {
+                write!(f, "{}", grid[Pos { x, y }])?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+fn default_legend(tiles: &TileRegistry) -> HashMap<char, Symbol> {
+    let mut legend = HashMap::new();
+    for (id, tile) in tiles.iter() {
+        legend.entry(tile.glyph).or_insert(Symbol::Tile(id));
+    }
+    let on = TileId::GROUND;
+    for monster in all_monsters() {
+        let name = monster.name().to_owned();
+        legend.insert(monster.display_char(), Symbol::Entity { name, on });
+    }
+    for item in all_items() {
+        let name = item.name.to_owned();
+        legend.insert(item.display_char, Symbol::Entity { name, on });
+    }
+    let name = PLAYER.to_owned();
+    legend.insert('@', Symbol::Entity { name, on });
+    legend
+}
+
+fn legend_entry(line: &str) -> Option<(char, &str)> {
+    let mut chars = line.chars();
+    let c = chars.next()?;
+    let rest = chars.as_str();
+    if rest.starts_with(" = ") {
+        Some((c, rest[3..].trim()))
+    } else {
+        None
+    }
+}
+
+fn parse_symbol(value: &str, tiles: &TileRegistry) -> Result<Symbol, String> {
+    let mut parts = value.splitn(2, " on ");
+    let name = parts.next().unwrap_or("").trim();
+    let on = parts.next().map(str::trim);
+    if name.is_empty() {
+        return Err("legend entry has no name".to_owned());
+    }
+    match (tiles.id(name), on) {
+        (Some(id), None) => Ok(Symbol::Tile(id)),
+        (Some(_), Some(_)) => Err(format!("tile {} can't stand on another tile", name)),
+        (None, on) => {
+            let on = match on {
+                None => TileId::GROUND,
+                Some(on) => tiles.id(on).ok_or_else(|| format!("unknown tile {}", on))?,
+            };
+            Ok(Symbol::Entity {
+                name: name.to_owned(),
+                on,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::levels::races::orc_race;
+    use tcod::colors::RED;
+
+    #[test]
+    fn parses_tiles_and_placements() {
+        let map = AsciiMap::parse(
+            "
+            + = locked_door
+            k = kobold on rubble
+
+            #####
+            #@.k+
+            #o'##
+            ",
+        )
+        .unwrap();
+        let level = &map.level;
+        assert_eq!(
+            level.dim(),
+            Dim {
+                width: 5,
+                height: 3
+            }
+        );
+        assert_eq!(level.tile(Pos { x: 0, y: 0 }), TileId::WALL);
+        assert_eq!(level.tile(Pos { x: 1, y: 1 }), TileId::GROUND);
+        assert_eq!(level.tile(Pos { x: 3, y: 1 }), TileId::RUBBLE);
+        assert_eq!(level.tile(Pos { x: 4, y: 1 }), TileId::LOCKED_DOOR);
+        assert_eq!(level.tile(Pos { x: 2, y: 2 }), TileId::OPEN_DOOR);
+        assert_eq!(map.player_pos(), Some(Pos { x: 1, y: 1 }));
+        assert_eq!(
+            map.find("kobold").collect::<Vec<_>>(),
+            vec![Pos { x: 3, y: 1 }]
+        );
+        assert_eq!(
+            map.find("orc").collect::<Vec<_>>(),
+            vec![Pos { x: 1, y: 2 }]
+        );
+    }
+
+    #[test]
+    fn reports_broken_maps() {
+        let line = |source: &str| AsciiMap::parse(source).unwrap_err().line;
+        assert_eq!(line("x = unicorn on cloud\n###"), 1);
+        assert_eq!(line("# = wall on ground\n###"), 1);
+        assert_eq!(line("###\n##\n###"), 2);
+        assert_eq!(line("###\n#?#"), 2);
+        assert_eq!(line("x = wall\n"), 1);
+    }
+
+    #[test]
+    fn writes_what_it_reads() {
+        let source = "
+            + = locked_door
+            k = kobold on rubble
+            ~ = lava
+
+            ######
+            #@.k+#
+            #o~'~#
+            ######
+        ";
+        let map = AsciiMap::parse(source).unwrap();
+        let text = map.to_string();
+        let back = AsciiMap::parse(&text).unwrap();
+        assert_eq!(back.level.dim(), map.level.dim());
+        for p in map.level.iter_pos() {
+            assert_eq!(back.level.tile(p), map.level.tile(p), "{}", text);
+        }
+        assert_eq!(back.placements, map.placements);
+    }
+
+    #[test]
+    fn tiles_sharing_glyph_get_own_characters() {
+        let mut level = LevelInfo::new(3, 1);
+        level.set(Pos { x: 0, y: 0 }, TileId::SHALLOW_WATER);
+        level.set(Pos { x: 1, y: 0 }, TileId::DEEP_WATER);
+        level.set(Pos { x: 2, y: 0 }, TileId::LAVA);
+        let text = AsciiMap::new(level).to_string();
+        let back = AsciiMap::parse(&text).unwrap();
+        assert_eq!(back.level.tile(Pos { x: 1, y: 0 }), TileId::DEEP_WATER);
+        assert_eq!(back.level.tile(Pos { x: 2, y: 0 }), TileId::LAVA);
+    }
+
+    #[test]
+    fn dumps_world() {
+        let map = AsciiMap::parse("@.o").unwrap();
+        let mut world = World::new();
+        world.register::<IsVisible>();
+        world.register::<IsPlayer>();
+        world.register::<HasPos>();
+        world.register::<HasVision>();
+        world.register::<IsFighter>();
+        world.register::<HasBrain>();
+        world.register::<HasPerception>();
+        world.register::<HasName>();
+        world.add_resource(map.level.clone());
+        world
+            .create_entity()
+            .is_player()
+            .with_actor_components('@', RED, map.player_pos().unwrap())
+            .build();
+        for pos in map.find("orc") {
+            world
+                .create_entity()
+                .is_monster(&orc_race().to_template(), pos)
+                .build();
+        }
+        assert_eq!(
+            AsciiMap::from_world(&world).to_string(),
+            "@ = player\n. = ground\no = orc\n\n@.o\n"
+        );
+    }
+}
@@ -1,25 +1,49 @@
 use crate::data::structures::pos::PosCollection;
 use crate::data::structures::*;
-use std::ops::Index;
-use std::ops::IndexMut;
+use rand::Rng;
 
-pub fn dig<M, P>(map: &mut M, positions: &P)
+pub fn dig<P>(level: &mut LevelInfo, positions: &P)
 where
     P: PosCollection,
-    M: IndexMut<Pos> + Index<Pos, Output = TileType>,
 {
     for p in positions.iter_pos() {
-        map[p] = TileType::Ground;
+        level.set(p, TileId::GROUND);
     }
 }
 
-pub fn put_walls<M, P>(map: &mut M, stru: &P)
+pub fn put_walls<P>(level: &mut LevelInfo, stru: &P)
 where
     P: HasWall,
-    M: IndexMut<Pos> + Index<Pos, Output = TileType>,
 {
     for p in stru.walls() {
-        map[p] = TileType::RoomWall;
+        level.set(p, TileId::ROOM_WALL);
+    }
+}
+
+/// Turns gaps that corridors made in walls of `stru` into doors, closed with `closed_chance`.
+/// A gap only counts when it has walls on both sides, so corridors running along a wall stay open
+pub fn put_doors<G, P>(level: &mut LevelInfo, stru: &P, closed_chance: f64, rng: &mut G)
+where
+    G: Rng,
+    P: HasWall,
+{
+    let blocks = |level: &LevelInfo, p: Pos, dir: Dir| {
+        level.step(p, dir).map_or(true, |n| level[n].blocks_sight())
+    };
+    for p in stru.walls() {
+        if level.tile(p) != TileId::GROUND {
+            continue;
+        }
+        let doorway = (blocks(level, p, N) && blocks(level, p, S))
+            || (blocks(level, p, E) && blocks(level, p, W));
+        if doorway {
+            let door = if rng.gen_bool(closed_chance) {
+                TileId::CLOSED_DOOR
+            } else {
+                TileId::OPEN_DOOR
+            };
+            level.set(p, door);
+        }
     }
 }
 
@@ -33,7 +57,7 @@ pub mod test {
     use std::cmp::{max, min};
 
     pub fn level_and_room() -> BoxedStrategy<(LevelInfo, Room)> {
-        (4..DimIndex::max_value(), 4..DimIndex::max_value())
+        (4..MAX_DIM.width, 4..MAX_DIM.height)
             .prop_map(|(h, w)| LevelInfo::new(h, w))
             .prop_flat_map(|m| {
                 let gen = room_in(m.dim());
@@ -43,7 +67,7 @@ pub mod test {
     }
 
     pub fn level_and_corridor() -> BoxedStrategy<(LevelInfo, LCorridor)> {
-        (4..DimIndex::max_value(), 4..DimIndex::max_value())
+        (4..MAX_DIM.width, 4..MAX_DIM.height)
             .prop_map(|(h, w)| LevelInfo::new(h, w))
             .prop_flat_map(|m| {
                 let pos_gen = pos_in_dim(m.dim());
@@ -54,15 +78,41 @@ pub mod test {
             .boxed()
     }
 
+    #[test]
+    fn gap_in_wall_becomes_door() {
+        let mut level = LevelInfo::new(5, 5);
+        let room = Room::new(
+            Pos { x: 1, y: 1 },
+            Dim {
+                width: 3,
+                height: 3,
+            },
+        );
+        dig(&mut level, &room);
+        put_walls(&mut level, &room);
+        let gap = Pos { x: 2, y: 0 };
+        level.set(gap, TileId::GROUND);
+        put_doors(&mut level, &room, 1.0, &mut rand::thread_rng());
+        assert_eq!(level.tile(gap), TileId::CLOSED_DOOR);
+        assert_eq!(
+            level
+                .iter_pos()
+                .filter(|&p| level.tile(p) != TileId::GROUND && !level[p].blocks_sight())
+                .count(),
+            0
+        );
+    }
+
     proptest! {
         #[test]
         fn room_is_diggable((mut level, room) in level_and_room()) {
-            prop_assert!(level.width() > room.to.x);
-            prop_assert!(level.height() > room.to.y);
-            let start_x = room.from.x;
-            let start_y = room.from.y;
-            let end_x = room.to.x;
-            let end_y = room.to.y;
+            let last = room.rect.max_pos();
+            prop_assert!(level.width() > last.x);
+            prop_assert!(level.height() > last.y);
+            let start_x = room.rect.from.x;
+            let start_y = room.rect.from.y;
+            let end_x = last.x;
+            let end_y = last.y;
             dig(&mut level, &room);
             for p in level.iter_pos() {
                 if p.x >= start_x && p.x <= end_x && p.y >= start_y && p.y <= end_y {
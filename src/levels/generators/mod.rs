@@ -1,14 +1,17 @@
 use std::ops::Range;
+use std::sync::Arc;
 
 use itertools::free::any;
 use log::trace;
+use rand::seq::SliceRandom;
 use rand::Rng;
 
 use crate::common::gen::Gen;
 use crate::data::structures::pos::PosCollection;
-use crate::data::structures::world_data::MonsterTemplate;
+use crate::data::structures::world_data::{ItemTemplate, MonsterTemplate};
 use crate::data::structures::*;
 use crate::levels::generators::mosters::MonsterGeneratorParam;
+use crate::levels::vaults::{PlacedVault, Vault};
 
 pub mod mosters;
 
@@ -16,8 +19,7 @@ pub mod mosters;
 pub struct RoomGenStrategy {
     pub min_dim: Dim,
     pub max_dim: Dim,
-    pub max_pos: Pos,
-    pub min_pos: Pos,
+    pub bounds: Rect,
 }
 
 impl Gen for Room {
@@ -30,8 +32,20 @@ impl Gen for Room {
         let width = rng.gen_range(param.min_dim.width, param.max_dim.width + 1);
         let height = rng.gen_range(param.min_dim.height, param.max_dim.height + 1);
         let dim = Dim { width, height };
-        let x = rng.gen_range(param.min_pos.x, param.max_pos.x - width + 1);
-        let y = rng.gen_range(param.min_pos.y, param.max_pos.y - height + 1);
+        let max_x = param
+            .bounds
+            .to
+            .x
+            .checked_sub(width)
+            .expect("Room is wider than bounds");
+        let max_y = param
+            .bounds
+            .to
+            .y
+            .checked_sub(height)
+            .expect("Room is higher than bounds");
+        let x = rng.gen_range(param.bounds.from.x, max_x + 1);
+        let y = rng.gen_range(param.bounds.from.y, max_y + 1);
         let pos = Pos { x, y };
         trace!("Generated {:?} with dim {:?} for {:?}", pos, dim, param);
         Room::new(pos, dim)
@@ -44,13 +58,23 @@ pub struct LevelGenStrategy {
     pub max_rooms: usize,
     pub monsters: Range<u8>,
     pub monster_strategy: MonsterGeneratorParam,
+    /// Chance for a room to have a lamp lighting it up
+    pub lit_rooms: f64,
+    /// Prefabs to pick from, each of them can show up several times
+    pub vaults: Arc<Vec<Vault>>,
+    pub max_vaults: usize,
 }
 
 pub struct Level {
     pub rooms: Vec<Room>,
     pub corridors: Vec<LCorridor>,
+    /// Vaults the corridors lead to, they still have to be stamped into the level
+    pub vaults: Vec<PlacedVault>,
     pub player_pos: Pos,
     pub monsters: Vec<(MonsterTemplate, Pos)>,
+    pub items: Vec<(ItemTemplate, Pos)>,
+    /// Positions and radiuses of lamps
+    pub lamps: Vec<(Pos, DimIndex)>,
 }
 
 impl Gen for Level {
@@ -60,35 +84,43 @@ impl Gen for Level {
     where
         G: Rng,
     {
+        let mut vaults: Vec<PlacedVault> = Vec::new();
+        for _ in 0..param.max_vaults {
+            let placed = match place_vault(rng, param) {
+                Some(placed) => placed,
+                None => continue,
+            };
+            let conflicts = any(vaults.iter(), |v| {
+                v.rect().inflate(1).intersects(&placed.rect())
+            });
+            if !conflicts {
+                vaults.push(placed);
+            }
+        }
+
         let mut rooms: Vec<Room> = Vec::new();
         for _ in 0..param.max_rooms {
             let room = Room::create(rng, &param.room_strategy);
-            let conflicts = any(rooms.iter(), |r| r.intersects(&room));
+            // Keeps room walls off the tiles in front of vault entrances
+            let conflicts = any(rooms.iter(), |r| r.intersects(&room))
+                || any(vaults.iter(), |v| {
+                    v.rect().inflate(1).intersects(&room.rect.inflate(1))
+                });
             if !conflicts {
                 rooms.push(room);
             }
         }
         rooms.sort_by(|a, b| a.center().cmp(&b.center()));
 
-        let mut corridors = Vec::new();
-        for i in 1..rooms.len() {
-            let room1 = rooms[i - 1];
-            let room2 = rooms[i];
-            let horizontal_first = rng.gen_bool(0.5);
-            trace!(
-                "{:?} - Connecting {:?}[{:?}] and {:?}[{:?}] with corridor",
-                i,
-                room1.center(),
-                room1,
-                room2.center(),
-                room2
-            );
-            corridors.push(LCorridor::new(
-                room1.center(),
-                room2.center(),
-                horizontal_first,
-            ));
-        }
+        let corridors = loop {
+            match connect(rng, &rooms, &vaults) {
+                Ok(corridors) => break corridors,
+                Err(blocked) => {
+                    trace!("Corridors can't get around {:?}", vaults[blocked].rect());
+                    vaults.remove(blocked);
+                }
+            }
+        };
 
         let player_pos = rooms[0].center();
 
@@ -109,15 +141,97 @@ impl Gen for Level {
             }
         }
 
+        monsters.extend(vaults.iter().flat_map(|v| v.monsters()));
+        let items = vaults.iter().flat_map(|v| v.items()).collect();
+
+        let lamps = rooms
+            .iter()
+            .filter(|_| rng.gen_bool(param.lit_rooms))
+            .map(|r| (r.center(), (r.rect.width() + r.rect.height()) / 2))
+            .collect();
+
         Level {
             rooms,
             corridors,
+            vaults,
             player_pos,
             monsters,
+            items,
+            lamps,
         }
     }
 }
 
+/// Randomly turned vault at a random place in bounds, if it fits there. A tile of the bounds is
+/// left free around it, so that corridors can reach its entrances
+fn place_vault<G>(rng: &mut G, param: &LevelGenStrategy) -> Option<PlacedVault>
+where
+    G: Rng,
+{
+    let mut vault = param.vaults.choose(rng)?.clone();
+    for _ in 0..rng.gen_range(0, 4) {
+        vault = vault.rotated();
+    }
+    if rng.gen_bool(0.5) {
+        vault = vault.mirrored();
+    }
+    let bounds = param.room_strategy.bounds.deflate(1);
+    let max_x = bounds.to.x.checked_sub(vault.dim().width)?;
+    let max_y = bounds.to.y.checked_sub(vault.dim().height)?;
+    if max_x < bounds.from.x || max_y < bounds.from.y {
+        return None;
+    }
+    let at = Pos {
+        x: rng.gen_range(bounds.from.x, max_x + 1),
+        y: rng.gen_range(bounds.from.y, max_y + 1),
+    };
+    Some(PlacedVault { vault, at })
+}
+
+/// Links room centers and tiles in front of vault entrances one after another. Corridors must
+/// not cut through vaults, when there's no way around one its index is returned
+fn connect<G>(rng: &mut G, rooms: &[Room], vaults: &[PlacedVault]) -> Result<Vec<LCorridor>, usize>
+where
+    G: Rng,
+{
+    let mut points = rooms
+        .iter()
+        .map(|r| r.center())
+        .chain(vaults.iter().flat_map(|v| v.approaches()))
+        .collect::<Vec<_>>();
+    points.sort();
+    points.dedup();
+
+    let blocked_by = |corridor: &LCorridor| {
+        corridor
+            .iter_pos()
+            .find_map(|p| vaults.iter().position(|v| v.rect().contains(p)))
+    };
+    let mut corridors = Vec::new();
+    for i in 1..points.len() {
+        let (from, to) = if rng.gen_bool(0.5) {
+            (points[i - 1], points[i])
+        } else {
+            (points[i], points[i - 1])
+        };
+        trace!("{:?} - Connecting {:?} and {:?} with corridor", i, from, to);
+        // Corridors go horizontally from `from` first, swapping the ends gives the other L
+        let corridor = LCorridor::new(from, to, true);
+        let corridor = match blocked_by(&corridor) {
+            None => corridor,
+            Some(blocked) => {
+                let other = LCorridor::new(to, from, true);
+                if blocked_by(&other).is_some() {
+                    return Err(blocked);
+                }
+                other
+            }
+        };
+        corridors.push(corridor);
+    }
+    Ok(corridors)
+}
+
 #[cfg(test)]
 mod test {
     use std::cmp::max;
@@ -129,6 +243,7 @@ mod test {
 
     use crate::data::structures::pos::test::*;
     use crate::levels::races::all_monsters;
+    use crate::levels::vaults::builtin_vaults;
 
     use super::*;
 
@@ -166,8 +281,7 @@ mod test {
                 RoomGenStrategy {
                     min_dim,
                     max_dim,
-                    min_pos,
-                    max_pos,
+                    bounds: Rect::inclusive(min_pos, max_pos),
                 }
             })
             .boxed()
@@ -181,19 +295,126 @@ mod test {
             monster_strategy: MonsterGeneratorParam {
                 templates: all_monsters(),
             },
+            lit_rooms: 0.5,
+            vaults: Arc::default(),
+            max_vaults: 0,
         })
     }
 
+    #[test]
+    fn vaults_stay_clear_of_rooms_and_corridors() {
+        let mut rng = rand::thread_rng();
+        let bounds = Rect::with_dim(
+            Pos { x: 1, y: 1 },
+            Dim {
+                width: 88,
+                height: 43,
+            },
+        );
+        let strategy = LevelGenStrategy {
+            room_strategy: RoomGenStrategy {
+                min_dim: Dim {
+                    width: 6,
+                    height: 6,
+                },
+                max_dim: Dim {
+                    width: 10,
+                    height: 10,
+                },
+                bounds,
+            },
+            max_rooms: 30,
+            monsters: 0..1,
+            monster_strategy: MonsterGeneratorParam {
+                templates: all_monsters(),
+            },
+            lit_rooms: 0.5,
+            vaults: Arc::new(builtin_vaults(TileRegistry::builtin())),
+            max_vaults: 3,
+        };
+        for _ in 0..20 {
+            let level = Level::create(&mut rng, &strategy);
+            for vault in &level.vaults {
+                let rect = vault.rect();
+                assert_eq!(rect.overlap(&bounds), Some(rect));
+                for room in &level.rooms {
+                    assert!(!room.rect.inflate(1).intersects(&rect));
+                }
+                for corridor in &level.corridors {
+                    assert!(!any(corridor.iter_pos(), |p| rect.contains(p)));
+                }
+                for p in vault.approaches() {
+                    assert!(bounds.contains(p));
+                    assert!(any(level.corridors.iter(), |c| c.from == p || c.to == p));
+                }
+                for (_, p) in vault.monsters() {
+                    assert!(any(level.monsters.iter(), |(_, m)| *m == p));
+                }
+                for (_, p) in vault.items() {
+                    assert!(any(level.items.iter(), |(_, i)| *i == p));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vault_approaches_stay_in_bounds() {
+        let mut rng = rand::thread_rng();
+        let vault = Vault::parse(
+            "
+            E = entrance on closed_door
+
+            #E#
+            E.E
+            #E#
+            ",
+        )
+        .unwrap();
+        let bounds = Rect::with_dim(
+            Pos { x: 1, y: 1 },
+            Dim {
+                width: 5,
+                height: 5,
+            },
+        );
+        let strategy = LevelGenStrategy {
+            room_strategy: RoomGenStrategy {
+                min_dim: Dim {
+                    width: 1,
+                    height: 1,
+                },
+                max_dim: Dim {
+                    width: 1,
+                    height: 1,
+                },
+                bounds,
+            },
+            max_rooms: 0,
+            monsters: 0..1,
+            monster_strategy: MonsterGeneratorParam {
+                templates: all_monsters(),
+            },
+            lit_rooms: 0.5,
+            vaults: Arc::new(vec![vault]),
+            max_vaults: 1,
+        };
+        for _ in 0..20 {
+            let placed = place_vault(&mut rng, &strategy).unwrap();
+            assert_eq!(placed.approaches().count(), 4);
+            assert!(placed.approaches().all(|p| bounds.contains(p)));
+        }
+    }
+
     proptest! {
 
         #[test]
         fn room_gen_generates_room_in_bounds(rgs in room_gen_strategy()) {
             let mut rng = rand::thread_rng();
             let room = Room::create(&mut rng, &rgs);
-            prop_assert!(room.from.x >= rgs.min_pos.x);
-            prop_assert!(room.from.y >= rgs.min_pos.y);
-            prop_assert!(room.to.x   <= rgs.max_pos.x);
-            prop_assert!(room.to.y   <= rgs.max_pos.y);
+            prop_assert!(room.rect.from.x >= rgs.bounds.from.x);
+            prop_assert!(room.rect.from.y >= rgs.bounds.from.y);
+            prop_assert!(room.rect.to.x   <= rgs.bounds.to.x);
+            prop_assert!(room.rect.to.y   <= rgs.bounds.to.y);
             prop_assert!(room.width() >= rgs.min_dim.width);
             prop_assert!(room.height() >= rgs.min_dim.height);
             prop_assert!(room.width() <= rgs.max_dim.width);
@@ -208,10 +429,10 @@ mod test {
             prop_assert!(rooms.rooms.len() <= rgs.max_rooms);
             let rgs = rgs.room_strategy;
             for room in rooms.rooms.clone() {
-                prop_assert!(room.from.x   >= rgs.min_pos.x);
-                prop_assert!(room.from.y   >= rgs.min_pos.y);
-                prop_assert!(room.to.x     <= rgs.max_pos.x);
-                prop_assert!(room.to.y     <= rgs.max_pos.y);
+                prop_assert!(room.rect.from.x >= rgs.bounds.from.x);
+                prop_assert!(room.rect.from.y >= rgs.bounds.from.y);
+                prop_assert!(room.rect.to.x   <= rgs.bounds.to.x);
+                prop_assert!(room.rect.to.y   <= rgs.bounds.to.y);
                 prop_assert!(room.width()  >= rgs.min_dim.width);
                 prop_assert!(room.height() >= rgs.min_dim.height);
                 prop_assert!(room.width()  <= rgs.max_dim.width);
@@ -0,0 +1,27 @@
+use tcod::colors::*;
+
+use crate::data::structures::world_data::ItemTemplate;
+
+pub fn potion() -> ItemTemplate {
+    ItemTemplate {
+        name: "potion",
+        display_char: '!',
+        color: LIGHT_RED,
+    }
+}
+
+pub fn gold() -> ItemTemplate {
+    ItemTemplate {
+        name: "gold",
+        display_char: '$',
+        color: AMBER,
+    }
+}
+
+pub fn all_items() -> Vec<ItemTemplate> {
+    vec![potion(), gold()]
+}
+
+pub fn item_named(name: &str) -> Option<ItemTemplate> {
+    all_items().into_iter().find(|i| i.name == name)
+}
@@ -1,30 +1,48 @@
 use rand::Rng;
+use specs::prelude::*;
+use std::cmp::{min, Reverse};
+use std::sync::Arc;
 
+use crate::common::dijkstra::DijkstraMap;
 use crate::common::gen::Gen;
+use crate::data::components::*;
 use crate::data::structures::matrix::*;
+use crate::data::structures::pos::PosCollection;
 use crate::data::structures::*;
 use crate::levels::generators::mosters::MonsterGeneratorParam;
 use crate::levels::generators::Level;
 use crate::levels::generators::LevelGenStrategy;
 use crate::levels::generators::RoomGenStrategy;
 use crate::levels::races::all_monsters;
+use crate::levels::vaults::{PlacedVault, Vault};
 
 pub use self::functions::*;
 
+pub mod ascii;
 pub mod functions;
 pub mod generators;
+pub mod items;
 pub mod races;
+pub mod vaults;
 
 const MAP_DIM: Dim = Dim {
     width: 90,
     height: 45,
 };
 
-pub fn level_1<G>(rng: &mut G) -> (LevelInfo, Level)
+/// Levels get more monsters, vaults and less light the deeper they are. `vaults` have to be made
+/// of `tiles`
+pub fn level_at<G>(
+    depth: Depth,
+    rng: &mut G,
+    tiles: Arc<TileRegistry>,
+    vaults: Arc<Vec<Vault>>,
+) -> (LevelInfo, Level)
 where
     G: Rng,
 {
-    let mut level = LevelInfo::with_dim(MAP_DIM);
+    let deeper = min(depth, 40) as u8;
+    let mut level = LevelInfo::with_tiles(tiles, Matrix::new(MAP_DIM.width, MAP_DIM.height));
     let strategy = LevelGenStrategy {
         room_strategy: RoomGenStrategy {
             max_dim: Dim {
@@ -35,14 +53,16 @@ where
                 height: 6,
                 width: 6,
             },
-            max_pos: level.max_pos().w().n(),
-            min_pos: Pos::default().e().s(),
+            bounds: Rect::with_dim(Pos::default(), level.dim()).deflate(1),
         },
         max_rooms: 30,
-        monsters: 20..30,
+        monsters: 15 + 5 * deeper..25 + 5 * deeper,
         monster_strategy: MonsterGeneratorParam {
             templates: all_monsters(),
         },
+        lit_rooms: (0.6 - 0.1 * depth as f64).max(0.1),
+        vaults,
+        max_vaults: min(1 + depth / 2, 3),
     };
 
     let rooms = Level::create(rng, &strategy);
@@ -53,6 +73,131 @@ where
     for corridor in &rooms.corridors {
         dig(&mut level, corridor);
     }
+    for vault in &rooms.vaults {
+        level.stamp(&vault.vault.tiles().as_view(), vault.at);
+    }
+    put_stairs(&mut level, rooms.player_pos, depth, &rooms.vaults);
+    for room in &rooms.rooms {
+        put_doors(&mut level, room, 0.7, rng);
+    }
 
     (level, rooms)
 }
+
+/// Player arrives on the up stairs, stairs down are on the ground as many steps away from them
+/// as it gets, counting closed doors as passable. Vaults are left as they were designed.
+/// There's nothing above the first level, so it doesn't get stairs up
+fn put_stairs(level: &mut LevelInfo, arrival: Pos, depth: Depth, vaults: &[PlacedVault]) {
+    let passable = LevelInfo::with_tiles(
+        level.tiles().clone(),
+        Matrix::tabulate(level.dim(), |p| {
+            level[p].opens_to.unwrap_or_else(|| level.tile(p))
+        }),
+    );
+    let steps = DijkstraMap::weighted(&passable, &arrival, |_, _| 1);
+    let down = level
+        .iter_pos()
+        .filter(|&p| level.tile(p) == TileId::GROUND)
+        .filter(|&p| !vaults.iter().any(|v| v.rect().contains(p)))
+        .filter_map(|p| steps.distance(p).map(|d| (d, p)))
+        .max_by_key(|&(d, p)| (d, Reverse(p)))
+        .map(|(_, p)| p);
+    if let Some(down) = down {
+        level.set(down, TileId::STAIRS_DOWN);
+    }
+    if depth > 1 {
+        level.set(arrival, TileId::STAIRS_UP);
+    }
+}
+
+/// Creates monsters, items and lamps of a freshly generated level
+pub fn populate(world: &mut World, level: &Level) {
+    for (monster, pos) in &level.monsters {
+        world.create_entity().is_monster(monster, *pos).build();
+    }
+    for (item, pos) in &level.items {
+        world.create_entity().is_item(item, *pos).build();
+    }
+    for &(pos, radius) in &level.lamps {
+        world.create_entity().is_lamp(pos, radius).build();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::common::path::AStar;
+    use crate::levels::ascii::AsciiMap;
+    use crate::levels::vaults::builtin_vaults;
+
+    use super::*;
+
+    fn stairs_down(level: &LevelInfo) -> Vec<Pos> {
+        level
+            .iter_pos()
+            .filter(|&p| level.tile(p) == TileId::STAIRS_DOWN)
+            .collect()
+    }
+
+    #[test]
+    fn stairs_down_go_through_doors_onto_ground_outside_vaults() {
+        let map = AsciiMap::parse(
+            "
+            #######
+            @.+...~
+            #######
+            ",
+        )
+        .unwrap();
+        let arrival = map.player_pos().unwrap();
+        let mut level = map.level.clone();
+        put_stairs(&mut level, arrival, 1, &[]);
+        assert_eq!(stairs_down(&level), vec![Pos { x: 5, y: 1 }]);
+
+        let vault = Vault::parse(
+            "
+            E = entrance on closed_door
+
+            #E#
+            #.#
+            ###
+            ",
+        )
+        .unwrap();
+        let placed = PlacedVault {
+            vault,
+            at: Pos { x: 4, y: 0 },
+        };
+        let mut level = map.level.clone();
+        put_stairs(&mut level, arrival, 2, &[placed]);
+        assert_eq!(stairs_down(&level), vec![Pos { x: 3, y: 1 }]);
+        assert_eq!(level.tile(arrival), TileId::STAIRS_UP);
+    }
+
+    #[test]
+    fn generated_level_is_connected() {
+        let mut rng = rand::thread_rng();
+        let tiles = TileRegistry::builtin();
+        let vaults = Arc::new(builtin_vaults(tiles.clone()));
+        let (level, generated) = level_at(5, &mut rng, tiles, vaults);
+        // Closed doors are opened on the way
+        let level = LevelInfo::from_matrix(Matrix::tabulate(level.dim(), |p| {
+            level[p].opens_to.unwrap_or_else(|| level.tile(p))
+        }));
+        let astar = AStar::new();
+        for room in &generated.rooms {
+            let path = astar.find(&level, generated.player_pos, room.center());
+            assert!(path.is_some(), "{:?} is not reachable", room);
+        }
+        for vault in &generated.vaults {
+            for p in vault.approaches() {
+                let path = astar.find(&level, generated.player_pos, p);
+                assert!(
+                    path.is_some(),
+                    "{:?} of {:?} is not reachable",
+                    p,
+                    vault.rect()
+                );
+            }
+        }
+    }
+}
@@ -6,20 +6,28 @@ use crate::data::structures::world_data::Race;
 
 pub fn orc_race() -> Race {
     Race {
+        name: "orc",
         default_char: 'o',
         default_color: DESATURATED_GREEN,
         default_fight_skill: IsFighter::new(10, 3, 0),
+        sight_radius: 7,
     }
 }
 
 pub fn troll_race() -> Race {
     Race {
+        name: "troll",
         default_char: 'T',
         default_color: DARKER_GREEN,
         default_fight_skill: IsFighter::new(16, 4, 1),
+        sight_radius: 5,
     }
 }
 
 pub fn all_monsters() -> Vec<MonsterTemplate> {
     vec![orc_race().to_template(), troll_race().to_template()]
 }
+
+pub fn monster_named(name: &str) -> Option<MonsterTemplate> {
+    all_monsters().into_iter().find(|m| m.name() == name)
+}
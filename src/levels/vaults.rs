@@ -0,0 +1,379 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::data::structures::matrix::Matrix;
+use crate::data::structures::world_data::{ItemTemplate, MonsterTemplate};
+use crate::data::structures::*;
+use crate::levels::ascii::{AsciiMap, MapError};
+use crate::levels::items::item_named;
+use crate::levels::races::monster_named;
+
+/// Name of the placements marking where corridors come into a vault
+pub const ENTRANCE: &str = "entrance";
+
+const BUILTIN: [(&str, &str); 3] = [
+    (
+        "guard_post",
+        include_str!("../../static/vaults/guard_post.txt"),
+    ),
+    (
+        "lava_moat",
+        include_str!("../../static/vaults/lava_moat.txt"),
+    ),
+    (
+        "flooded_crypt",
+        include_str!("../../static/vaults/flooded_crypt.txt"),
+    ),
+];
+
+#[derive(Debug)]
+pub enum VaultError {
+    Io(io::Error),
+    Map(MapError),
+    Invalid(String),
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VaultError::Io(e) => write!(f, "can't read vault: {}", e),
+            VaultError::Map(e) => write!(f, "can't parse vault: {}", e),
+            VaultError::Invalid(message) => write!(f, "broken vault: {}", message),
+        }
+    }
+}
+
+impl Error for VaultError {}
+
+impl From<io::Error> for VaultError {
+    fn from(e: io::Error) -> Self {
+        VaultError::Io(e)
+    }
+}
+
+impl From<MapError> for VaultError {
+    fn from(e: MapError) -> Self {
+        VaultError::Map(e)
+    }
+}
+
+/// Hand-designed part of a level, written as an ASCII map with its own walls. Entrances are
+/// placements named `entrance` on the edge of the map, other placements spawn the monster or
+/// item of that name
+#[derive(Debug, Clone)]
+pub struct Vault {
+    tiles: Matrix<TileId>,
+    monsters: Vec<(MonsterTemplate, Pos)>,
+    items: Vec<(ItemTemplate, Pos)>,
+    entrances: Vec<Pos>,
+}
+
+impl Vault {
+    /// Vault of the builtin tiles
+    pub fn parse(source: &str) -> Result<Self, VaultError> {
+        Self::parse_with(source, TileRegistry::builtin())
+    }
+
+    pub fn parse_with(source: &str, tiles: Arc<TileRegistry>) -> Result<Self, VaultError> {
+        let map = AsciiMap::parse_with(source, tiles)?;
+        let dim = map.level.dim();
+        let mut monsters = vec![];
+        let mut items = vec![];
+        let mut entrances = vec![];
+        for placement in &map.placements {
+            if placement.name == ENTRANCE {
+                if edge_of(dim, placement.pos).is_none() {
+                    return Err(VaultError::Invalid(format!(
+                        "entrance at {:?} is not on a side of the vault",
+                        placement.pos
+                    )));
+                }
+                entrances.push(placement.pos);
+            } else if let Some(monster) = monster_named(&placement.name) {
+                monsters.push((monster, placement.pos));
+            } else if let Some(item) = item_named(&placement.name) {
+                items.push((item, placement.pos));
+            } else {
+                return Err(VaultError::Invalid(format!(
+                    "can't place {}, it is neither a monster, an item nor an entrance",
+                    placement.name
+                )));
+            }
+        }
+        if entrances.is_empty() {
+            return Err(VaultError::Invalid("vault has no entrance".to_owned()));
+        }
+        let level = &map.level;
+        Ok(Vault {
+            tiles: Matrix::tabulate(dim, |p| level.tile(p)),
+            monsters,
+            items,
+            entrances,
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        tiles: Arc<TileRegistry>,
+    ) -> Result<Self, VaultError> {
+        Self::parse_with(&fs::read_to_string(path)?, tiles)
+    }
+
+    pub fn dim(&self) -> Dim {
+        self.tiles.dim()
+    }
+
+    pub fn tiles(&self) -> &Matrix<TileId> {
+        &self.tiles
+    }
+
+    pub fn monsters(&self) -> &[(MonsterTemplate, Pos)] {
+        &self.monsters
+    }
+
+    pub fn items(&self) -> &[(ItemTemplate, Pos)] {
+        &self.items
+    }
+
+    pub fn entrances(&self) -> &[Pos] {
+        &self.entrances
+    }
+
+    /// Turned a quarter clockwise
+    pub fn rotated(&self) -> Self {
+        let height = self.tiles.height();
+        let turn = |p: Pos| Pos {
+            x: height - 1 - p.y,
+            y: p.x,
+        };
+        let dim = Dim {
+            width: height,
+            height: self.tiles.width(),
+        };
+        self.transformed(dim, turn, |p| Pos {
+            x: p.y,
+            y: height - 1 - p.x,
+        })
+    }
+
+    /// Flipped left to right
+    pub fn mirrored(&self) -> Self {
+        let width = self.tiles.width();
+        let flip = move |p: Pos| Pos {
+            x: width - 1 - p.x,
+            y: p.y,
+        };
+        self.transformed(self.dim(), flip, flip)
+    }
+
+    /// `to` moves a position of this vault to the new one, `from` does the opposite
+    fn transformed<T, F>(&self, dim: Dim, to: T, from: F) -> Self
+    where
+        T: Fn(Pos) -> Pos,
+        F: Fn(Pos) -> Pos,
+    {
+        Vault {
+            tiles: Matrix::tabulate(dim, |p| self.tiles[from(p)]),
+            monsters: self
+                .monsters
+                .iter()
+                .map(|(m, p)| (m.clone(), to(*p)))
+                .collect(),
+            items: self
+                .items
+                .iter()
+                .map(|(i, p)| (i.clone(), to(*p)))
+                .collect(),
+            entrances: self.entrances.iter().map(|&p| to(p)).collect(),
+        }
+    }
+}
+
+/// Vaults new levels are built with, loaded once at startup
+#[derive(Debug, Clone, Default)]
+pub struct Vaults(pub Arc<Vec<Vault>>);
+
+/// Vault stamped into a level at `at`
+#[derive(Debug, Clone)]
+pub struct PlacedVault {
+    pub vault: Vault,
+    pub at: Pos,
+}
+
+impl PlacedVault {
+    pub fn rect(&self) -> Rect {
+        Rect::with_dim(self.at, self.vault.dim())
+    }
+
+    /// Tiles just outside of the entrances, where corridors should end
+    pub fn approaches(&self) -> impl Iterator<Item = Pos> + '_ {
+        let dim = self.vault.dim();
+        self.vault
+            .entrances
+            .iter()
+            .filter_map(move |&p| self.absolute(p) + edge_of(dim, p)?)
+    }
+
+    /// Monsters of the vault with their positions on the level
+    pub fn monsters(&self) -> impl Iterator<Item = (MonsterTemplate, Pos)> + '_ {
+        self.vault
+            .monsters
+            .iter()
+            .map(move |(m, p)| (m.clone(), self.absolute(*p)))
+    }
+
+    /// Items of the vault with their positions on the level
+    pub fn items(&self) -> impl Iterator<Item = (ItemTemplate, Pos)> + '_ {
+        self.vault
+            .items
+            .iter()
+            .map(move |(i, p)| (i.clone(), self.absolute(*p)))
+    }
+
+    fn absolute(&self, p: Pos) -> Pos {
+        Pos {
+            x: self.at.x + p.x,
+            y: self.at.y + p.y,
+        }
+    }
+}
+
+/// Direction out of the vault for positions on its side, corners don't count
+fn edge_of(dim: Dim, p: Pos) -> Option<Dir> {
+    let last = dim.max_pos();
+    let sides = [
+        (p.x == 0, W),
+        (p.x == last.x, E),
+        (p.y == 0, N),
+        (p.y == last.y, S),
+    ];
+    let mut out = sides.iter().filter(|(on, _)| *on).map(|(_, dir)| *dir);
+    match (out.next(), out.next()) {
+        (Some(dir), None) => Some(dir),
+        _ => None,
+    }
+}
+
+/// Vaults of `static/vaults` as they were at build time
+pub fn builtin_vaults(tiles: Arc<TileRegistry>) -> Vec<Vault> {
+    BUILTIN
+        .iter()
+        .map(|(name, source)| {
+            Vault::parse_with(source, tiles.clone())
+                .unwrap_or_else(|e| panic!("static/vaults/{}: {}", name, e))
+        })
+        .collect()
+}
+
+/// Every `.txt` vault of the directory, made of `tiles`
+pub fn load_vaults<P: AsRef<Path>>(
+    dir: P,
+    tiles: Arc<TileRegistry>,
+) -> Result<Vec<Vault>, VaultError> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|p| p.extension().map_or(false, |e| e == "txt"));
+    paths.sort();
+    paths
+        .iter()
+        .map(|p| Vault::from_file(p, tiles.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vault() -> Vault {
+        Vault::parse(
+            "
+            E = entrance on closed_door
+
+            #E##
+            #!o#
+            ####
+            ",
+        )
+        .unwrap()
+    }
+
+    fn cells(v: &Vault) -> Vec<(Pos, TileId)> {
+        v.tiles().iter().map(|(p, &t)| (p, t)).collect()
+    }
+
+    #[test]
+    fn builtin_vaults_are_valid() {
+        let tiles = TileRegistry::builtin();
+        assert_eq!(builtin_vaults(tiles.clone()).len(), BUILTIN.len());
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/static/vaults");
+        let from_dir = load_vaults(dir, tiles).unwrap();
+        assert_eq!(from_dir.len(), BUILTIN.len());
+    }
+
+    #[test]
+    fn rotating_moves_everything_together() {
+        let v = vault().rotated();
+        assert_eq!(
+            v.dim(),
+            Dim {
+                width: 3,
+                height: 4
+            }
+        );
+        assert_eq!(v.entrances(), &[Pos { x: 2, y: 1 }]);
+        assert_eq!(v.tiles()[Pos { x: 2, y: 1 }], TileId::CLOSED_DOOR);
+        assert_eq!(v.monsters()[0].1, Pos { x: 1, y: 2 });
+        assert_eq!(v.items()[0].1, Pos { x: 1, y: 1 });
+        assert_eq!(v.tiles()[Pos { x: 1, y: 2 }], TileId::GROUND);
+        let back = v.rotated().rotated().rotated();
+        assert_eq!(cells(&back), cells(&vault()));
+        assert_eq!(back.entrances(), vault().entrances());
+    }
+
+    #[test]
+    fn mirroring_flips_sides() {
+        let v = vault().mirrored();
+        assert_eq!(v.entrances(), &[Pos { x: 2, y: 0 }]);
+        assert_eq!(v.monsters()[0].1, Pos { x: 1, y: 1 });
+        assert_eq!(v.items()[0].1, Pos { x: 2, y: 1 });
+        assert_eq!(v.tiles()[Pos { x: 2, y: 0 }], TileId::CLOSED_DOOR);
+        assert_eq!(cells(&v.mirrored()), cells(&vault()));
+    }
+
+    #[test]
+    fn corridors_come_to_entrances_from_outside() {
+        let placed = PlacedVault {
+            vault: vault().rotated(),
+            at: Pos { x: 5, y: 5 },
+        };
+        assert_eq!(
+            placed.approaches().collect::<Vec<_>>(),
+            vec![Pos { x: 8, y: 6 }]
+        );
+        assert!(!placed.rect().contains(Pos { x: 8, y: 6 }));
+        assert_eq!(placed.monsters().next().unwrap().1, Pos { x: 6, y: 7 });
+        assert_eq!(placed.items().next().unwrap().1, Pos { x: 6, y: 6 });
+    }
+
+    #[test]
+    fn broken_vaults_are_rejected() {
+        let invalid = |source: &str| match Vault::parse(source) {
+            Err(VaultError::Invalid(_)) => {}
+            other => panic!("{:?}", other.map(|v| v.dim())),
+        };
+        invalid("###\n#.#\n###");
+        invalid("E = entrance\n\n###\n#E#\n###");
+        invalid("E = entrance\n\nE##\n#.#\n###");
+        invalid("E = entrance\n\n#E#\n#@#\n###");
+        invalid("E = entrance\nd = dragon\n\n#E#\n#d#\n###");
+        match Vault::parse("E = entrance\n\n#E#\n##") {
+            Err(VaultError::Map(e)) => assert_eq!(e.line, 4),
+            other => panic!("{:?}", other.map(|v| v.dim())),
+        }
+    }
+}
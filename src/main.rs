@@ -2,6 +2,8 @@ extern crate doryen_rs;
 extern crate rogue_tutorial;
 extern crate specs;
 
+use std::sync::Arc;
+
 use doryen_rs::{App, AppOptions};
 use specs::prelude::*;
 use tcod::colors::RED;
@@ -9,7 +11,11 @@ use tcod::colors::RED;
 use rogue_tutorial::compatibility::doryen::*;
 use rogue_tutorial::data::components::*;
 use rogue_tutorial::data::structures::*;
-use rogue_tutorial::levels::level_1;
+use rogue_tutorial::levels::vaults::{load_vaults, Vaults};
+use rogue_tutorial::levels::{level_at, populate};
+
+const TILES_PATH: &str = "static/tiles.toml";
+const VAULTS_PATH: &str = "static/vaults";
 
 const CONSOLE_DIM: Dim = Dim {
     width: 90,
@@ -21,24 +27,32 @@ fn main() {
     world.register::<HasPos>();
     world.register::<IsVisible>();
     world.register::<IsPlayer>();
+    world.register::<IsItem>();
     world.register::<PlansExecuting>();
     world.register::<HasVision>();
+    world.register::<EmitsLight>();
+    world.register::<HasPerception>();
+    world.register::<HasName>();
 
     let mut rng = rand::thread_rng();
     // FXIME Extract to script
-    let (level_info, level) = level_1(&mut rng);
+    let tiles = TileRegistry::load(TILES_PATH).unwrap_or_else(|e| panic!("{}: {}", TILES_PATH, e));
+    let vaults = load_vaults(VAULTS_PATH, tiles.clone())
+        .unwrap_or_else(|e| panic!("{}: {}", VAULTS_PATH, e));
+    let vaults = Arc::new(vaults);
+    let (level_info, level) = level_at(1, &mut rng, tiles, vaults.clone());
 
     world.add_resource(level_info);
+    world.add_resource(Vaults(vaults));
 
     world
         .create_entity()
         .is_player()
+        .with(EmitsLight::torch())
         .with_actor_components('@', RED, level.player_pos)
         .build();
 
-    for (monster, pos) in level.monsters {
-        world.create_entity().is_monster(&monster, pos).build();
-    }
+    populate(&mut world, &level);
 
     let mut app = App::new(AppOptions {
         console_width: CONSOLE_DIM.width.into(),
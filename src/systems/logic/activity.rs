@@ -0,0 +1,388 @@
+use std::ops::Index;
+
+use specs::prelude::*;
+
+use crate::common::dijkstra::DijkstraMap;
+use crate::common::path::AStar;
+use crate::data::components::*;
+use crate::data::structures::bit_matrix::BitMatrix;
+use crate::data::structures::ALL_DIRS;
+use crate::data::structures::*;
+
+/// Turns player activities into one planned step per tick, stopping them when it's no longer safe
+/// or nothing is left to do
+pub struct PlanActivities;
+
+impl<'a> System<'a> for PlanActivities {
+    type SystemData = (
+        Read<'a, LevelInfo>,
+        Read<'a, SpatialIndex>,
+        Entities<'a>,
+        ReadStorage<'a, HasPos>,
+        ReadStorage<'a, HasVision>,
+        ReadStorage<'a, HasBrain>,
+        ReadStorage<'a, IsFighter>,
+        WriteStorage<'a, HasActivity>,
+        WriteStorage<'a, PlansExecuting>,
+        Write<'a, MessageLog>,
+    );
+
+    fn run(
+        &mut self,
+        (level, index, entity, pos, vision, brain, fighter, mut activity, mut plans, mut log): <Self as System<
+            'a,
+        >>::SystemData,
+    ) {
+        let mut stopped = vec![];
+        let mut planned = vec![];
+
+        for (e, pos, vision, activity) in (&entity, &pos, &vision, &mut activity).join() {
+            if plans.get(e).is_some() {
+                continue;
+            }
+            let (fov, memory) = match (vision.fov(), vision.memory()) {
+                (Some(fov), Some(memory)) => (fov, memory),
+                _ => continue,
+            };
+            let monster_in_view = fov.ones().any(|p| {
+                index
+                    .entities_at(p)
+                    .iter()
+                    .any(|&other| brain.get(other).is_some())
+            });
+            if monster_in_view {
+                stopped.push((e, "A monster comes into view"));
+                continue;
+            }
+
+            let next = match activity.0 {
+                Activity::Explore { ref mut path } => explore_step(&level, memory, pos.0, path),
+                Activity::Travel {
+                    target,
+                    ref mut path,
+                } => {
+                    let blocked = |p| index.blocking_at(p, &fighter).is_some();
+                    travel_step(&level, memory, pos.0, target, path, blocked)
+                }
+            };
+            match next {
+                Ok(next) if index.blocking_at(next, &fighter).is_some() => {
+                    stopped.push((e, "The way is blocked"))
+                }
+                Ok(next) => {
+                    let dir = Dir::between(pos.0, next).expect("PlanActivities::step");
+                    planned.push((e, ActorCommand::Move(dir)));
+                }
+                Err(reason) => stopped.push((e, reason)),
+            }
+        }
+
+        for (e, reason) in stopped {
+            log::info!("Entity {:?} stops {:?}: {}", e, activity.get(e), reason);
+            activity.remove(e);
+            log.push(reason);
+        }
+        for (e, command) in planned {
+            plans
+                .insert(e, PlansExecuting::new(command))
+                .expect("PlanActivities::insert");
+        }
+    }
+}
+
+/// Remembered walkable tiles next to tiles never seen
+pub fn frontier(level: &LevelInfo, memory: &BitMatrix) -> Vec<Pos> {
+    memory
+        .ones()
+        .filter(|&p| is_frontier(level, memory, p))
+        .collect()
+}
+
+fn is_frontier(level: &LevelInfo, memory: &BitMatrix, p: Pos) -> bool {
+    (level[p].is_walkable() || level[p].is_openable())
+        && ALL_DIRS
+            .iter()
+            .filter_map(|&dir| level.step(p, dir))
+            .any(|n| !memory[n])
+}
+
+/// Level as the player remembers it, tiles never seen are treated as walls.
+/// Doors the player can open are planned through as if they were open already
+struct KnownLevel<'a> {
+    level: &'a LevelInfo,
+    memory: &'a BitMatrix,
+}
+
+impl<'a> Index<Pos> for KnownLevel<'a> {
+    type Output = TileDef;
+    fn index(&self, p: Pos) -> &TileDef {
+        let tiles = self.level.tiles();
+        if !self.memory[p] {
+            &tiles[TileId::WALL]
+        } else {
+            &tiles[self.level[p].opens_to.unwrap_or_else(|| self.level.tile(p))]
+        }
+    }
+}
+
+impl<'a> HasDim for KnownLevel<'a> {
+    fn width(&self) -> DimIndex {
+        self.level.width()
+    }
+
+    fn height(&self) -> DimIndex {
+        self.level.height()
+    }
+}
+
+/// Drops the step the player has just taken, a step that failed is kept to be tried again
+fn advance(path: &mut Vec<Pos>, from: Pos) {
+    if path.last() == Some(&from) {
+        path.pop();
+    }
+}
+
+fn is_next_step(path: &[Pos], from: Pos) -> bool {
+    path.last()
+        .map_or(false, |&next| Dir::between(from, next).is_some())
+}
+
+/// Next step towards the closest frontier tile, walking only through remembered tiles.
+/// The path is planned again once its end is explored or it no longer starts next to `from`
+fn explore_step(
+    level: &LevelInfo,
+    memory: &BitMatrix,
+    from: Pos,
+    path: &mut Vec<Pos>,
+) -> Result<Pos, &'static str> {
+    advance(path, from);
+    let planned = is_next_step(path, from)
+        && path
+            .first()
+            .map_or(false, |&goal| is_frontier(level, memory, goal));
+    if !planned {
+        let targets = frontier(level, memory);
+        if targets.is_empty() {
+            return Err("Nothing left to explore");
+        }
+        let map = DijkstraMap::new(&KnownLevel { level, memory }, &targets);
+        path.clear();
+        let mut current = from;
+        while let Some(next) = map.downhill(current) {
+            path.push(next);
+            current = next;
+        }
+        path.reverse();
+    }
+    path.last().cloned().ok_or("Nothing left to explore")
+}
+
+/// Next step of the shortest known path to `target`, planned around blocked positions when the
+/// travel starts. The planned path is kept, so travel stops if it gets blocked later
+fn travel_step<F>(
+    level: &LevelInfo,
+    memory: &BitMatrix,
+    from: Pos,
+    target: Pos,
+    path: &mut Vec<Pos>,
+    blocked: F,
+) -> Result<Pos, &'static str>
+where
+    F: Fn(Pos) -> bool,
+{
+    if from == target {
+        return Err("You arrive at your destination");
+    }
+    advance(path, from);
+    if !is_next_step(path, from) {
+        let found = AStar::new()
+            .with_diagonals(true)
+            .with_blocker(blocked)
+            .find(&KnownLevel { level, memory }, from, target)
+            .ok_or("The way is blocked")?;
+        *path = found.steps;
+        path.reverse();
+    }
+    let next = *path.last().expect("travel_step::path");
+    if level[next].is_walkable() || level[next].is_openable() {
+        Ok(next)
+    } else {
+        Err("The way is blocked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, RunNow, World};
+    use tcod::colors::YELLOW;
+
+    use crate::data::structures::matrix::Matrix;
+    use crate::levels::races::orc_race;
+    use crate::systems::logic::spatial::tests::index_positions;
+    use crate::systems::logic::Fov;
+
+    use super::*;
+
+    fn create_world(width: DimIndex) -> (World, Entity) {
+        create_world_with(Dim { width, height: 1 })
+    }
+
+    fn create_world_with(dim: Dim) -> (World, Entity) {
+        let mut w = World::new();
+        let level = Matrix::tabulate(dim, |_| TileId::GROUND);
+        w.add_resource(LevelInfo::from_matrix(level));
+        <PlanActivities as System>::setup(&mut PlanActivities, &mut w.res);
+        <Fov as System>::setup(&mut Fov::default(), &mut w.res);
+        w.register::<IsVisible>();
+        w.register::<IsPlayer>();
+        w.register::<HasPerception>();
+        w.register::<HasName>();
+        let player = w
+            .create_entity()
+            .is_player()
+            .with_actor_components('@', YELLOW, Pos { x: 0, y: 0 })
+            .with(HasActivity(Activity::explore()))
+            .build();
+        (w, player)
+    }
+
+    fn plan(w: &mut World) {
+        Fov::default().run_now(&w.res);
+        index_positions(w);
+        PlanActivities.run_now(&w.res);
+    }
+
+    #[test]
+    fn explore_walks_towards_unseen() {
+        let (mut w, player) = create_world(20);
+        plan(&mut w);
+        assert_eq!(
+            w.read_storage::<PlansExecuting>().get(player),
+            Some(&PlansExecuting(ActorCommand::Move(E)))
+        );
+        assert!(w.read_storage::<HasActivity>().get(player).is_some());
+    }
+
+    #[test]
+    fn explore_stops_when_everything_is_seen() {
+        let (mut w, player) = create_world(3);
+        plan(&mut w);
+        assert_eq!(w.read_storage::<PlansExecuting>().get(player), None);
+        assert_eq!(w.read_storage::<HasActivity>().get(player), None);
+    }
+
+    #[test]
+    fn explore_stops_when_monster_is_visible() {
+        let (mut w, player) = create_world(20);
+        w.create_entity()
+            .is_monster(&orc_race().to_template(), Pos { x: 5, y: 0 })
+            .build();
+        plan(&mut w);
+        assert_eq!(w.read_storage::<PlansExecuting>().get(player), None);
+        assert_eq!(w.read_storage::<HasActivity>().get(player), None);
+    }
+
+    #[test]
+    fn explore_stops_when_monster_is_visible_with_unlimited_sight() {
+        let (mut w, player) = create_world(40);
+        w.write_storage::<HasVision>()
+            .insert(player, HasVision::new(0))
+            .unwrap();
+        w.create_entity()
+            .is_monster(&orc_race().to_template(), Pos { x: 30, y: 0 })
+            .build();
+        plan(&mut w);
+        assert_eq!(w.read_storage::<PlansExecuting>().get(player), None);
+        assert_eq!(w.read_storage::<HasActivity>().get(player), None);
+        let log = w.read_resource::<MessageLog>();
+        let messages: Vec<_> = log.latest(1).collect();
+        assert_eq!(messages, vec!["A monster comes into view"]);
+    }
+
+    #[test]
+    fn travel_walks_to_target_and_stops() {
+        let (mut w, player) = create_world(5);
+        let target = Pos { x: 1, y: 0 };
+        w.write_storage::<HasActivity>()
+            .insert(player, HasActivity(Activity::travel(target)))
+            .unwrap();
+        plan(&mut w);
+        assert_eq!(
+            w.read_storage::<PlansExecuting>().get(player),
+            Some(&PlansExecuting(ActorCommand::Move(E)))
+        );
+
+        w.write_storage::<PlansExecuting>().remove(player);
+        w.write_storage::<HasPos>()
+            .insert(player, HasPos(target))
+            .unwrap();
+        plan(&mut w);
+        assert_eq!(w.read_storage::<HasActivity>().get(player), None);
+        let log = w.read_resource::<MessageLog>();
+        let messages: Vec<_> = log.latest(1).collect();
+        assert_eq!(messages, vec!["You arrive at your destination"]);
+    }
+
+    #[test]
+    fn travel_stops_when_path_is_blocked() {
+        let (mut w, player) = create_world(5);
+        w.create_entity()
+            .with(HasPos(Pos { x: 1, y: 0 }))
+            .with(IsFighter::new(1, 1, 1))
+            .build();
+        w.write_storage::<HasActivity>()
+            .insert(player, HasActivity(Activity::travel(Pos { x: 3, y: 0 })))
+            .unwrap();
+        plan(&mut w);
+        assert_eq!(w.read_storage::<PlansExecuting>().get(player), None);
+        assert_eq!(w.read_storage::<HasActivity>().get(player), None);
+        let log = w.read_resource::<MessageLog>();
+        let messages: Vec<_> = log.latest(1).collect();
+        assert_eq!(messages, vec!["The way is blocked"]);
+    }
+
+    #[test]
+    fn travel_stops_instead_of_going_around_a_new_blocker() {
+        let (mut w, player) = create_world_with(Dim {
+            width: 5,
+            height: 3,
+        });
+        let target = Pos { x: 4, y: 0 };
+        w.write_storage::<HasActivity>()
+            .insert(player, HasActivity(Activity::travel(target)))
+            .unwrap();
+        plan(&mut w);
+        assert_eq!(
+            w.read_storage::<PlansExecuting>().get(player),
+            Some(&PlansExecuting(ActorCommand::Move(E)))
+        );
+
+        w.write_storage::<PlansExecuting>().remove(player);
+        w.write_storage::<HasPos>()
+            .insert(player, HasPos(Pos { x: 1, y: 0 }))
+            .unwrap();
+        w.create_entity()
+            .with(HasPos(Pos { x: 2, y: 0 }))
+            .with(IsFighter::new(1, 1, 1))
+            .build();
+        plan(&mut w);
+        assert_eq!(w.read_storage::<PlansExecuting>().get(player), None);
+        assert_eq!(w.read_storage::<HasActivity>().get(player), None);
+        let log = w.read_resource::<MessageLog>();
+        let messages: Vec<_> = log.latest(1).collect();
+        assert_eq!(messages, vec!["The way is blocked"]);
+    }
+
+    #[test]
+    fn frontier_is_next_to_unknown() {
+        let level = LevelInfo::from_matrix(Matrix::tabulate(
+            Dim {
+                width: 4,
+                height: 1,
+            },
+            |_| TileId::GROUND,
+        ));
+        let memory = BitMatrix::tabulate(level.dim(), |p| p.x < 2);
+        assert_eq!(frontier(&level, &memory), vec![Pos { x: 1, y: 0 }]);
+    }
+}
@@ -1,22 +1,225 @@
-use specs::Entities;
-use specs::Read;
-use specs::ReadStorage;
-use specs::System;
+use std::collections::HashSet;
 
+use specs::prelude::*;
+
+use crate::common::dijkstra::DijkstraMap;
+use crate::common::path::AStar;
+use crate::common::query::singleton;
 use crate::data::components::*;
 use crate::data::structures::*;
 
+/// Monsters who see the player walk towards them and attack, badly hurt ones run away.
+/// Both directions come from Dijkstra maps shared by all monsters. Monsters who lost sight of
+/// the player go to where they saw them last, unaware ones stay
 pub struct GetAiCommand;
 
 impl<'a> System<'a> for GetAiCommand {
-    type SystemData = (Read<'a, LevelInfo>, ReadStorage<'a, HasBrain>, Entities<'a>);
-    fn run(&mut self, (_level, brain, entity): <Self as System<'a>>::SystemData) {
-        use specs::Join;
-        for (_brain, e) in (&brain, &entity).join() {
-            log::debug!(
-                "Entity {:?} thinks of what doing next and stays stubborn",
-                e
-            );
+    type SystemData = (
+        Read<'a, LevelInfo>,
+        Read<'a, SpatialIndex>,
+        Entities<'a>,
+        ReadStorage<'a, HasBrain>,
+        ReadStorage<'a, HasPos>,
+        ReadStorage<'a, IsPlayer>,
+        ReadStorage<'a, IsFighter>,
+        WriteStorage<'a, HasPerception>,
+        WriteStorage<'a, PlansExecuting>,
+    );
+
+    fn run(
+        &mut self,
+        (level, index, entity, brain, pos, is_pl, fighter, mut perception, mut plans): <Self as System<
+            'a,
+        >>::SystemData,
+    ) {
+        let level = &*level;
+        let (player, player_pos, _) = match singleton((&entity, &pos, &is_pl)) {
+            Ok(player) => player,
+            Err(()) => return,
+        };
+        let player_pos = player_pos.0;
+
+        let mut claimed = HashSet::new();
+        if let Some(PlansExecuting(ActorCommand::Move(dir))) = plans.get(player) {
+            claimed.extend(player_pos + *dir);
+        }
+
+        let mut approach: Option<DijkstraMap> = None;
+        let mut flee: Option<DijkstraMap> = None;
+        let mut decisions = vec![];
+
+        for (e, _brain, pos, f, perception) in
+            (&entity, &brain, &pos, &fighter, &mut perception).join()
+        {
+            let next = match perception.awareness {
+                Awareness::Unaware => {
+                    log::trace!("Entity {:?} doesn't know about the player and stays", e);
+                    continue;
+                }
+                Awareness::Sees(_) => {
+                    let approach: &DijkstraMap =
+                        approach.get_or_insert_with(|| DijkstraMap::new(level, &player_pos));
+                    let map: &DijkstraMap = if f.is_badly_hurt() {
+                        flee.get_or_insert_with(|| approach.inverted(level, 1.2))
+                    } else {
+                        approach
+                    };
+                    map.downhill(pos.0)
+                }
+                Awareness::Remembers(last_seen) => {
+                    let step = AStar::new()
+                        .with_diagonals(true)
+                        .find(level, pos.0, last_seen)
+                        .and_then(|path| path.first_step());
+                    if step.is_none() {
+                        perception.awareness = Awareness::Unaware;
+                    }
+                    step
+                }
+            };
+            let next = match next {
+                Some(next) => next,
+                None => continue,
+            };
+
+            let command = if next == player_pos {
+                ActorCommand::MeleeAttack {
+                    pos: next,
+                    target: player,
+                }
+            } else if claimed.contains(&next) || index.blocking_at(next, &fighter).is_some() {
+                log::debug!("Entity {:?} waits for {:?} to free up", e, next);
+                continue;
+            } else {
+                claimed.insert(next);
+                ActorCommand::Move(Dir::between(pos.0, next).expect("GetAiCommand::downhill"))
+            };
+            log::debug!("Entity {:?} decided to {:?}", e, command);
+            decisions.push((e, command));
+        }
+
+        for (e, command) in decisions {
+            plans
+                .insert(e, PlansExecuting::new(command))
+                .expect("GetAiCommand::insert");
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, RunNow, World};
+    use tcod::colors::YELLOW;
+
+    use crate::data::structures::matrix::Matrix;
+    use crate::levels::races::orc_race;
+    use crate::systems::logic::spatial::tests::index_positions;
+    use crate::systems::logic::Perception;
+
+    use super::*;
+
+    fn create_world(width: DimIndex) -> World {
+        let mut w = World::new();
+        let level = Matrix::tabulate(Dim { width, height: 1 }, |_| TileId::GROUND);
+        w.add_resource(LevelInfo::from_matrix(level));
+        <GetAiCommand as System>::setup(&mut GetAiCommand, &mut w.res);
+        <Perception as System>::setup(&mut Perception, &mut w.res);
+        w.register::<IsVisible>();
+        w.register::<HasVision>();
+        w.register::<HasName>();
+        w.create_entity()
+            .is_player()
+            .with_actor_components('@', YELLOW, Pos { x: 0, y: 0 })
+            .build();
+        w
+    }
+
+    fn decide(w: &mut World) {
+        Perception.run_now(&w.res);
+        index_positions(w);
+        GetAiCommand.run_now(&w.res);
+    }
+
+    fn add_orc(w: &mut World, x: DimIndex) -> Entity {
+        w.create_entity()
+            .is_monster(&orc_race().to_template(), Pos { x, y: 0 })
+            .build()
+    }
+
+    #[test]
+    fn visible_monster_approaches() {
+        let mut w = create_world(5);
+        let orc = add_orc(&mut w, 3);
+        decide(&mut w);
+        assert_eq!(
+            w.read_storage::<PlansExecuting>().get(orc),
+            Some(&PlansExecuting(ActorCommand::Move(W)))
+        );
+    }
+
+    #[test]
+    fn adjacent_monster_attacks() {
+        let mut w = create_world(5);
+        let orc = add_orc(&mut w, 1);
+        decide(&mut w);
+        let command = w.read_storage::<PlansExecuting>().get(orc).map(|p| p.0);
+        match command {
+            Some(ActorCommand::MeleeAttack { pos, .. }) => assert_eq!(pos, Pos { x: 0, y: 0 }),
+            other => panic!("Expected attack, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn monster_out_of_sight_stays() {
+        let mut w = create_world(20);
+        let orc = add_orc(&mut w, 19);
+        decide(&mut w);
+        assert_eq!(w.read_storage::<PlansExecuting>().get(orc), None);
+    }
+
+    #[test]
+    fn monsters_dont_claim_same_cell() {
+        let mut w = create_world(5);
+        let front = add_orc(&mut w, 2);
+        let back = add_orc(&mut w, 3);
+        decide(&mut w);
+        let plans = w.read_storage::<PlansExecuting>();
+        assert_eq!(
+            plans.get(front),
+            Some(&PlansExecuting(ActorCommand::Move(W)))
+        );
+        assert_eq!(plans.get(back), None);
+    }
+
+    #[test]
+    fn badly_hurt_monster_flees() {
+        let mut w = create_world(5);
+        let orc = add_orc(&mut w, 2);
+        {
+            let mut fighters = w.write_storage::<IsFighter>();
+            let f = fighters.get_mut(orc).unwrap();
+            f.current_hp = 1;
+        }
+        decide(&mut w);
+        assert_eq!(
+            w.read_storage::<PlansExecuting>().get(orc),
+            Some(&PlansExecuting(ActorCommand::Move(E)))
+        );
+    }
+
+    #[test]
+    fn monster_goes_where_player_was_last_seen() {
+        let mut w = create_world(20);
+        let orc = add_orc(&mut w, 5);
+        w.write_storage::<HasPerception>()
+            .get_mut(orc)
+            .unwrap()
+            .awareness = Awareness::Remembers(Pos { x: 8, y: 0 });
+        index_positions(&mut w);
+        GetAiCommand.run_now(&w.res);
+        assert_eq!(
+            w.read_storage::<PlansExecuting>().get(orc),
+            Some(&PlansExecuting(ActorCommand::Move(E)))
+        );
+    }
+}
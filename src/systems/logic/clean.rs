@@ -1,4 +1,5 @@
 use crate::data::components::IsFighter;
+use crate::data::components::IsPlayer;
 use specs::Entities;
 use specs::LazyUpdate;
 use specs::Read;
@@ -12,12 +13,16 @@ impl<'a> System<'a> for Clean {
         Read<'a, LazyUpdate>,
         Entities<'a>,
         ReadStorage<'a, IsFighter>,
+        ReadStorage<'a, IsPlayer>,
     );
 
-    fn run(&mut self, (lazy, entity, hp): <Self as System<'a>>::SystemData) {
+    fn run(&mut self, (lazy, entity, hp, is_pl): <Self as System<'a>>::SystemData) {
         use specs::Join;
         for (entity, hp) in (&entity, &hp).join() {
-            if hp.is_dead() {
+            if hp.is_dead() && is_pl.get(entity).is_some() {
+                // TODO - game over screen, until then the player stays on the map
+                log::warn!("Player {:?} is dead", hp);
+            } else if hp.is_dead() {
                 log::info!("Entity {:?} - {:?} is dead, removing", entity, hp);
                 lazy.exec_mut(move |w| {
                     log::trace!("Removing entity {:?}", entity);
@@ -18,6 +18,7 @@ impl<'a> System<'a> for ExecuteEffects {
         for (e, hp, dam) in (&ent, &mut hp, &dam).join() {
             log::info!("Dealing {:?} to {:?}", dam, hp);
             hp.inflict_damage(dam.attack_power);
+            hp.inflict_terrain_damage(dam.terrain_damage);
             log::trace!("Result: {:?}", hp);
             lazy.remove::<HasEffectStack>(e);
         }
@@ -1,26 +1,83 @@
 use super::*;
 use crate::common::fov::*;
+use crate::data::structures::change_log::Version;
 use log::trace;
 use std::collections::HashMap;
 
 pub struct Fov {
     fov_validity_cache: HashMap<Entity, Pos>,
+    /// Level and its version the cached FOVs were calculated for
+    level_seen: Option<(LevelId, LevelVersion)>,
+    /// Version of `LightMap` the cached FOVs were calculated for
+    light_seen: Version,
+}
+
+impl Fov {
+    /// Drops cached FOVs which terrain or light changes made stale
+    fn invalidate<'a>(
+        &mut self,
+        level: &LevelInfo,
+        light: &LightMap,
+        e: &Entities<'a>,
+        vis: &WriteStorage<'a, HasVision>,
+    ) {
+        let level_changes: Option<Vec<Rect>> = match self.level_seen {
+            Some((id, version)) if id == level.id() => {
+                level.changes_since(version).map(Iterator::collect)
+            }
+            _ => None,
+        };
+        let light_changes: Option<Vec<Rect>> =
+            light.changes_since(self.light_seen).map(Iterator::collect);
+        self.level_seen = Some((level.id(), level.version()));
+        self.light_seen = light.version();
+
+        let changes = match (level_changes, light_changes) {
+            (Some(mut level_changes), Some(light_changes)) => {
+                level_changes.extend(light_changes);
+                level_changes
+            }
+            _ => {
+                self.fov_validity_cache.clear();
+                return;
+            }
+        };
+        if changes.is_empty() {
+            return;
+        }
+        let stale: Vec<Entity> = (e, vis)
+            .join()
+            .filter_map(|(e, vis)| vis.fov().map(|fov| (e, fov.bounds())))
+            .filter(|(_, seen)| changes.iter().any(|c| c.intersects(seen)))
+            .map(|(e, _)| e)
+            .collect();
+        for e in stale {
+            trace!("Terrain or light changed in view of {:?}", e);
+            self.fov_validity_cache.remove(&e);
+        }
+    }
 }
 
 impl<'a> System<'a> for Fov {
     type SystemData = (
         Read<'a, LevelInfo>,
+        Read<'a, LightMap>,
         Entities<'a>,
         ReadStorage<'a, HasPos>,
         WriteStorage<'a, HasVision>,
     );
 
-    fn run(&mut self, (level, e, pos, mut vis): <Self as System<'a>>::SystemData) {
+    fn run(&mut self, (level, light, e, pos, mut vis): <Self as System<'a>>::SystemData) {
         use specs::Join;
 
         let level = &*level;
+        let new_level = self.level_seen.map(|(id, _)| id) != Some(level.id());
+        self.invalidate(level, &light, &e, &vis);
 
         for (e, pos, vis) in (&e, &pos, &mut vis).join() {
+            if new_level {
+                vis.enter_level(level.id());
+            }
             let known_pos = self.fov_validity_cache.get(&e);
             trace!("Pos for {:?} is {:?}, current is {:?}", e, known_pos, pos.0);
             let is_cache_valid = match known_pos {
@@ -29,7 +86,9 @@ impl<'a> System<'a> for Fov {
             };
 
             if !is_cache_valid {
-                let new_fov = calculate_fov(level, pos.0, vis.radius);
+                let mut new_fov = calculate_fov_with(level, pos.0, vis.radius, vis.algorithm);
+                // Dark tiles stay unseen, but everyone knows where they stand
+                new_fov.retain(|p| p == pos.0 || light.is_lit(p));
                 vis.set_fov(new_fov);
                 self.fov_validity_cache.insert(e, pos.0);
             }
@@ -41,6 +100,135 @@ impl Default for Fov {
     fn default() -> Self {
         Fov {
             fov_validity_cache: HashMap::default(),
+            level_seen: None,
+            light_seen: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, RunNow, World};
+
+    use crate::data::structures::matrix::Matrix;
+
+    use super::*;
+
+    fn create_world() -> (World, Fov, Entity, Entity) {
+        let mut w = World::new();
+        let level = Matrix::tabulate(
+            Dim {
+                width: 30,
+                height: 30,
+            },
+            |_| TileId::GROUND,
+        );
+        w.add_resource(LevelInfo::from_matrix(level));
+        let mut fov = Fov::default();
+        <Fov as System>::setup(&mut fov, &mut w.res);
+        let near = w
+            .create_entity()
+            .with(HasPos(Pos { x: 2, y: 2 }))
+            .with(HasVision::new(4))
+            .build();
+        let far = w
+            .create_entity()
+            .with(HasPos(Pos { x: 25, y: 25 }))
+            .with(HasVision::new(4))
+            .build();
+        fov.run_now(&w.res);
+        // Smaller radius shows whether FOV was calculated again
+        for e in vec![near, far] {
+            w.write_storage::<HasVision>().get_mut(e).unwrap().radius = 2;
+        }
+        (w, fov, near, far)
+    }
+
+    fn fov_bounds(w: &World, e: Entity) -> Rect {
+        w.read_storage::<HasVision>()
+            .get(e)
+            .and_then(HasVision::fov)
+            .unwrap()
+            .bounds()
+    }
+
+    #[test]
+    fn recalculates_only_viewers_seeing_changed_tiles() {
+        let (w, mut fov, near, far) = create_world();
+        w.write_resource::<LevelInfo>()
+            .set(Pos { x: 3, y: 2 }, TileId::WALL);
+        fov.run_now(&w.res);
+
+        let near_vision = w.read_storage::<HasVision>();
+        let near_fov = near_vision.get(near).and_then(HasVision::fov).unwrap();
+        assert!(near_fov[Pos { x: 3, y: 2 }]);
+        assert!(!near_fov[Pos { x: 4, y: 2 }]);
+        assert_eq!(fov_bounds(&w, far).width(), 9);
+    }
+
+    #[test]
+    fn recalculates_everyone_on_new_level() {
+        let (mut w, mut fov, near, far) = create_world();
+        w.add_resource(LevelInfo::from_matrix(Matrix::tabulate(
+            Dim {
+                width: 30,
+                height: 30,
+            },
+            |_| TileId::GROUND,
+        )));
+        fov.run_now(&w.res);
+        assert_eq!(fov_bounds(&w, near).width(), 5);
+        assert_eq!(fov_bounds(&w, far).width(), 5);
+        let vision = w.read_storage::<HasVision>();
+        let memory = vision.get(near).and_then(HasVision::memory).unwrap();
+        assert!(!memory[Pos { x: 6, y: 2 }]);
+    }
+
+    #[test]
+    fn dark_tiles_stay_unseen() {
+        let (w, mut fov, near, _) = create_world();
+        let dim = w.read_resource::<LevelInfo>().dim();
+        {
+            let mut light = w.write_resource::<LightMap>();
+            light.reset(dim);
+            light.update(Rect::with_dim(Pos { x: 0, y: 0 }, dim), |p| {
+                if p.x <= 3 {
+                    Light::full()
+                } else {
+                    Light::default()
+                }
+            });
+        }
+        fov.run_now(&w.res);
+        let vision = w.read_storage::<HasVision>();
+        let near_fov = vision.get(near).and_then(HasVision::fov).unwrap();
+        assert!(near_fov[Pos { x: 3, y: 2 }]);
+        assert!(!near_fov[Pos { x: 4, y: 2 }]);
+    }
+
+    #[test]
+    fn recalculates_only_viewers_seeing_changed_light() {
+        let (w, mut fov, near, far) = create_world();
+        let dim = w.read_resource::<LevelInfo>().dim();
+        {
+            let mut light = w.write_resource::<LightMap>();
+            light.reset(dim);
+            light.update(Rect::with_dim(Pos { x: 0, y: 0 }, dim), |_| Light::full());
         }
+        fov.run_now(&w.res);
+        for e in vec![near, far] {
+            w.write_storage::<HasVision>().get_mut(e).unwrap().radius = 1;
+        }
+        w.write_resource::<LightMap>().update(
+            Rect::inclusive(Pos { x: 3, y: 2 }, Pos { x: 3, y: 2 }),
+            |_| Light::default(),
+        );
+        fov.run_now(&w.res);
+
+        let vision = w.read_storage::<HasVision>();
+        let near_fov = vision.get(near).and_then(HasVision::fov).unwrap();
+        assert!(!near_fov[Pos { x: 3, y: 2 }]);
+        assert_eq!(fov_bounds(&w, near).width(), 3);
+        assert_eq!(fov_bounds(&w, far).width(), 5);
     }
 }
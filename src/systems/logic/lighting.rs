@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::common::fov::*;
+use crate::data::structures::matrix::Matrix;
+
+use super::*;
+
+/// Spreads light of every `EmitsLight` over tiles it reaches and stores the sum in `LightMap`.
+/// Light of an emitter is spread again only when it moves, changes, or terrain around it
+/// changes, and only the areas it lit before and after are summed up anew
+#[derive(Default)]
+pub struct Lighting {
+    emitted: HashMap<Entity, Emitted>,
+    /// Level and its version the emitted light was spread over
+    level_seen: Option<(LevelId, LevelVersion)>,
+}
+
+/// Light one emitter spreads from where it stands
+struct Emitted {
+    from: Pos,
+    emits: EmitsLight,
+    bounds: Rect,
+    lit: Vec<(Pos, Light)>,
+}
+
+impl Emitted {
+    fn spread(level: &LevelInfo, from: Pos, emits: &EmitsLight) -> Self {
+        let fov = calculate_fov_with(level, from, emits.radius, FovAlgorithm::Symmetric);
+        let lit = fov
+            .ones()
+            .map(|p| {
+                let intensity = emits.intensity_at(from.euclidean_distance(p));
+                (p, Light::from_color(emits.color, intensity))
+            })
+            .collect();
+        Emitted {
+            from,
+            emits: emits.clone(),
+            bounds: fov.bounds(),
+            lit,
+        }
+    }
+}
+
+impl Lighting {
+    /// Terrain changed since the last run, `None` when all of it has to be considered changed
+    fn terrain_changes(&mut self, level: &LevelInfo) -> Option<Vec<Rect>> {
+        let changes = match self.level_seen {
+            Some((id, version)) if id == level.id() => {
+                level.changes_since(version).map(Iterator::collect)
+            }
+            _ => None,
+        };
+        self.level_seen = Some((level.id(), level.version()));
+        changes
+    }
+}
+
+impl<'a> System<'a> for Lighting {
+    type SystemData = (
+        Read<'a, LevelInfo>,
+        Entities<'a>,
+        ReadStorage<'a, HasPos>,
+        ReadStorage<'a, EmitsLight>,
+        Write<'a, LightMap>,
+    );
+
+    fn run(
+        &mut self,
+        (level, entity, pos, emits, mut light_map): <Self as System<'a>>::SystemData,
+    ) {
+        let level = &*level;
+        let new_level = self.level_seen.map(|(id, _)| id) != Some(level.id());
+        let terrain_changes = self.terrain_changes(level);
+        if new_level {
+            self.emitted.clear();
+            light_map.reset(level.dim());
+        }
+
+        let mut dirty = vec![];
+        let mut present = HashSet::new();
+        for (e, pos, emits) in (&entity, &pos, &emits).join() {
+            present.insert(e);
+            let stale = match (self.emitted.get(&e), &terrain_changes) {
+                (None, _) | (_, None) => true,
+                (Some(old), Some(changes)) => {
+                    old.from != pos.0
+                        || old.emits != *emits
+                        || changes.iter().any(|c| c.intersects(&old.bounds))
+                }
+            };
+            if stale {
+                let new = Emitted::spread(level, pos.0, emits);
+                dirty.push(new.bounds);
+                if let Some(old) = self.emitted.insert(e, new) {
+                    if !dirty.contains(&old.bounds) {
+                        dirty.push(old.bounds);
+                    }
+                }
+            }
+        }
+        let gone: Vec<Entity> = self
+            .emitted
+            .keys()
+            .filter(|e| !present.contains(e))
+            .cloned()
+            .collect();
+        for e in gone {
+            dirty.extend(self.emitted.remove(&e).map(|old| old.bounds));
+        }
+
+        for area in dirty {
+            let local = |p: Pos| Pos {
+                x: p.x - area.from.x,
+                y: p.y - area.from.y,
+            };
+            let mut lights = Matrix::tabulate(area.dim(), |_| Light::default());
+            for emitted in self
+                .emitted
+                .values()
+                .filter(|em| em.bounds.intersects(&area))
+            {
+                for &(p, light) in emitted.lit.iter().filter(|(p, _)| area.contains(*p)) {
+                    lights[local(p)] += light;
+                }
+            }
+            light_map.update(area, |p| lights[local(p)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, RunNow, World};
+
+    use super::*;
+
+    fn create_world() -> World {
+        let mut w = World::new();
+        let mut level = Matrix::tabulate(
+            Dim {
+                width: 20,
+                height: 1,
+            },
+            |_| TileId::GROUND,
+        );
+        level[Pos { x: 10, y: 0 }] = TileId::WALL;
+        w.add_resource(LevelInfo::from_matrix(level));
+        <Lighting as System>::setup(&mut Lighting::default(), &mut w.res);
+        w
+    }
+
+    #[test]
+    fn light_fades_and_stops_at_walls() {
+        let mut w = create_world();
+        w.create_entity()
+            .with(HasPos(Pos { x: 7, y: 0 }))
+            .with(EmitsLight::torch())
+            .build();
+        Lighting::default().run_now(&w.res);
+        let map = w.read_resource::<LightMap>();
+        let at = |x| map.light_at(Pos { x, y: 0 });
+        assert!(at(7).intensity() > at(8).intensity());
+        assert!(map.is_lit(Pos { x: 10, y: 0 }));
+        assert!(!map.is_lit(Pos { x: 11, y: 0 }));
+        assert!(!map.is_lit(Pos { x: 0, y: 0 }));
+    }
+
+    #[test]
+    fn lights_add_up() {
+        let mut w = create_world();
+        for x in &[2, 4] {
+            w.create_entity()
+                .with(HasPos(Pos { x: *x, y: 0 }))
+                .with(EmitsLight::torch())
+                .build();
+        }
+        Lighting::default().run_now(&w.res);
+        let map = w.read_resource::<LightMap>();
+        let single = EmitsLight::torch().intensity_at(1.0);
+        assert!(map.light_at(Pos { x: 3, y: 0 }).intensity() > single);
+    }
+
+    #[test]
+    fn moved_light_relights_old_and_new_place() {
+        let mut w = create_world();
+        let torch = w
+            .create_entity()
+            .with(HasPos(Pos { x: 2, y: 0 }))
+            .with(EmitsLight::torch())
+            .build();
+        let mut lighting = Lighting::default();
+        lighting.run_now(&w.res);
+        let version = w.read_resource::<LightMap>().version();
+        lighting.run_now(&w.res);
+        assert_eq!(w.read_resource::<LightMap>().version(), version);
+
+        w.write_storage::<HasPos>()
+            .insert(torch, HasPos(Pos { x: 16, y: 0 }))
+            .unwrap();
+        lighting.run_now(&w.res);
+        let map = w.read_resource::<LightMap>();
+        assert!(!map.is_lit(Pos { x: 2, y: 0 }));
+        assert!(map.is_lit(Pos { x: 16, y: 0 }));
+        assert!(map.is_lit(Pos { x: 11, y: 0 }));
+    }
+
+    #[test]
+    fn terrain_change_relights_only_lights_around_it() {
+        let mut w = create_world();
+        for x in &[1, 14] {
+            w.create_entity()
+                .with(HasPos(Pos { x: *x, y: 0 }))
+                .with(EmitsLight::torch())
+                .build();
+        }
+        let mut lighting = Lighting::default();
+        lighting.run_now(&w.res);
+        let version = w.read_resource::<LightMap>().version();
+        w.write_resource::<LevelInfo>()
+            .set(Pos { x: 10, y: 0 }, TileId::GROUND);
+        lighting.run_now(&w.res);
+        let map = w.read_resource::<LightMap>();
+        let changes: Vec<Rect> = map.changes_since(version).unwrap().collect();
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].contains(Pos { x: 14, y: 0 }));
+        assert!(!changes[0].contains(Pos { x: 1, y: 0 }));
+        assert!(map.is_lit(Pos { x: 9, y: 0 }));
+    }
+}
@@ -5,16 +5,24 @@ use specs::prelude::*;
 use crate::data::components::*;
 use crate::data::structures::*;
 
+pub use self::activity::*;
 pub use self::ai::*;
 pub use self::clean::*;
 pub use self::damage::*;
 pub use self::fov::*;
+pub use self::lighting::*;
+pub use self::perception::*;
+pub use self::spatial::*;
 use specs::error::Error;
 
+pub mod activity;
 pub mod ai;
 pub mod clean;
 pub mod damage;
 pub mod fov;
+pub mod lighting;
+pub mod perception;
+pub mod spatial;
 
 #[derive(Default)]
 pub struct AssertUnique<T: Component> {
@@ -38,21 +46,40 @@ impl<'a> System<'a> for ExecuteCommands {
         WriteStorage<'a, PlansExecuting>,
         WriteStorage<'a, HasEffectStack>,
         ReadStorage<'a, IsFighter>,
+        Write<'a, LevelInfo>,
         Read<'a, LazyUpdate>,
     );
 
     fn run(
         &mut self,
-        (e, mut pos, mut plan_storage, mut dam_storage, fighter, lu): Self::SystemData,
+        (e, mut pos, mut plan_storage, mut dam_storage, fighter, mut level, lu): Self::SystemData,
     ) {
         use specs::Join;
 
         for (e, pos, plan) in (&e, &mut pos, &mut plan_storage).join() {
-            let mut pos = &mut pos.0;
             match plan.0 {
-                ActorCommand::Move(ref dir) => {
-                    pos += dir;
-                }
+                ActorCommand::Move(dir) => match level.step(pos.0, dir) {
+                    // Walking into a closed door opens it and takes the turn
+                    Some(new_pos) if level[new_pos].is_openable() => {
+                        log::info!("Entity {:?} opens door at {:?}", e, new_pos);
+                        if let Some(opened) = level[new_pos].opens_to {
+                            level.set(new_pos, opened);
+                        }
+                    }
+                    Some(new_pos) => {
+                        pos.0 = new_pos;
+                        let damage = level[new_pos].damage_on_enter();
+                        if damage > 0 {
+                            log::info!("Entity {:?} takes {} damage at {:?}", e, damage, new_pos);
+                            dam_storage
+                                .entry(e)
+                                .expect("ExecuteCommand::Move")
+                                .or_insert_with(HasEffectStack::default)
+                                .terrain_damage += damage;
+                        }
+                    }
+                    None => log::warn!("Entity {:?} can't move {:?} from {:?}", e, dir, pos.0),
+                },
                 ActorCommand::MeleeAttack { pos, target } => {
                     log::info!("Atacking {:?} at {:?}", target, pos);
                     let f = fighter.get(e).expect("ExecuteCommand::MeleeAttack");
@@ -128,6 +155,7 @@ mod tests {
             let mut w = World::new();
             let mut s = ExecuteCommands;
             <ExecuteCommands as System>::setup(&mut s, &mut w.res);
+            w.add_resource(LevelInfo::new(3, 3));
 
             let e = w
                 .create_entity()
@@ -145,5 +173,113 @@ mod tests {
             );
             assert_eq!(w.read_storage::<PlansExecuting>().get(e), None);
         }
+
+        #[test]
+        fn move_off_the_map_is_rejected() {
+            let mut w = World::new();
+            let mut s = ExecuteCommands;
+            <ExecuteCommands as System>::setup(&mut s, &mut w.res);
+            w.add_resource(LevelInfo::new(3, 3));
+
+            let e = w
+                .create_entity()
+                .with(HasPos(Pos { x: 2, y: 1 }))
+                .with(PlansExecuting::new(ActorCommand::Move(E)))
+                .build();
+
+            ExecuteCommands.run_now(&w.res);
+
+            w.maintain();
+
+            assert_eq!(
+                w.read_storage::<HasPos>().get(e),
+                Some(&HasPos(Pos { x: 2, y: 1 }))
+            );
+            assert_eq!(w.read_storage::<PlansExecuting>().get(e), None);
+        }
+
+        #[test]
+        fn move_into_closed_door_opens_it() {
+            let mut w = World::new();
+            let mut s = ExecuteCommands;
+            <ExecuteCommands as System>::setup(&mut s, &mut w.res);
+            let mut level = LevelInfo::new(2, 1);
+            level.set(Pos { x: 1, y: 0 }, TileId::CLOSED_DOOR);
+            w.add_resource(level);
+
+            let e = w
+                .create_entity()
+                .with(HasPos(Pos { x: 0, y: 0 }))
+                .with(PlansExecuting::new(ActorCommand::Move(E)))
+                .build();
+
+            ExecuteCommands.run_now(&w.res);
+
+            assert_eq!(
+                w.read_storage::<HasPos>().get(e),
+                Some(&HasPos(Pos { x: 0, y: 0 }))
+            );
+            assert_eq!(
+                w.read_resource::<LevelInfo>().tile(Pos { x: 1, y: 0 }),
+                TileId::OPEN_DOOR
+            );
+        }
+
+        #[test]
+        fn lava_burns_past_defense() {
+            let mut w = World::new();
+            let mut s = ExecuteCommands;
+            <ExecuteCommands as System>::setup(&mut s, &mut w.res);
+            <ExecuteEffects as System>::setup(&mut ExecuteEffects, &mut w.res);
+            let mut level = LevelInfo::new(2, 2);
+            level.set(Pos { x: 1, y: 0 }, TileId::LAVA);
+            w.add_resource(level);
+
+            let e = w
+                .create_entity()
+                .with(HasPos(Pos { x: 0, y: 0 }))
+                .with(IsFighter::new(30, 1, 5))
+                .with(PlansExecuting::new(ActorCommand::Move(E)))
+                .build();
+            let attacker = w
+                .create_entity()
+                .with(HasPos(Pos { x: 0, y: 1 }))
+                .with(IsFighter::new(30, 7, 0))
+                .with(PlansExecuting::new(ActorCommand::MeleeAttack {
+                    pos: Pos { x: 0, y: 0 },
+                    target: e,
+                }))
+                .build();
+
+            ExecuteCommands.run_now(&w.res);
+            ExecuteEffects.run_now(&w.res);
+
+            let burn = TileRegistry::builtin()[TileId::LAVA].damage_on_enter();
+            let fighters = w.read_storage::<IsFighter>();
+            assert_eq!(fighters.get(e).unwrap().current_hp, 30 - burn - (7 - 5));
+            assert_eq!(fighters.get(attacker).unwrap().current_hp, 30);
+        }
+
+        #[test]
+        fn move_never_wraps() {
+            let mut w = World::new();
+            let mut s = ExecuteCommands;
+            <ExecuteCommands as System>::setup(&mut s, &mut w.res);
+
+            let e = w
+                .create_entity()
+                .with(HasPos(Pos { x: 0, y: 1 }))
+                .with(PlansExecuting::new(ActorCommand::Move(W)))
+                .build();
+
+            ExecuteCommands.run_now(&w.res);
+
+            w.maintain();
+
+            assert_eq!(
+                w.read_storage::<HasPos>().get(e),
+                Some(&HasPos(Pos { x: 0, y: 1 }))
+            );
+        }
     }
 }
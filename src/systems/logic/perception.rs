@@ -0,0 +1,136 @@
+use crate::common::fov::*;
+use crate::common::query::singleton;
+
+use super::*;
+
+/// Updates what monsters know about the player. Symmetric FOV means that instead of calculating
+/// FOV for every monster it's enough to calculate it once from the player
+pub struct Perception;
+
+impl<'a> System<'a> for Perception {
+    type SystemData = (
+        Read<'a, LevelInfo>,
+        Read<'a, LightMap>,
+        ReadStorage<'a, HasPos>,
+        ReadStorage<'a, IsPlayer>,
+        WriteStorage<'a, HasPerception>,
+    );
+
+    fn run(
+        &mut self,
+        (level, light, pos, is_pl, mut perception): <Self as System<'a>>::SystemData,
+    ) {
+        let player_pos = match singleton((&pos, &is_pl)) {
+            Ok((p, _)) => p.0,
+            Err(()) => return,
+        };
+        let max_radius = match (&perception).join().map(|p| p.sight_radius).max() {
+            Some(radius) => radius,
+            None => return,
+        };
+        let seen = calculate_fov_with(&*level, player_pos, max_radius, FovAlgorithm::Symmetric);
+        let player_lit = light.is_lit(player_pos);
+
+        for (pos, perception) in (&pos, &mut perception).join() {
+            let sees = player_lit
+                && seen[pos.0]
+                && pos.0.euclidean_distance(player_pos) <= f64::from(perception.sight_radius);
+            perception.awareness = match perception.awareness {
+                _ if sees => Awareness::Sees(player_pos),
+                Awareness::Sees(last_seen) => Awareness::Remembers(last_seen),
+                Awareness::Remembers(last_seen) if last_seen == pos.0 => {
+                    log::debug!("Lost track of the player at {:?}", last_seen);
+                    Awareness::Unaware
+                }
+                awareness => awareness,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, RunNow, World};
+
+    use crate::data::structures::matrix::Matrix;
+
+    use super::*;
+
+    fn create_world() -> (World, Entity, Entity) {
+        let mut w = World::new();
+        let mut level = Matrix::tabulate(
+            Dim {
+                width: 20,
+                height: 1,
+            },
+            |_| TileId::GROUND,
+        );
+        level[Pos { x: 10, y: 0 }] = TileId::WALL;
+        w.add_resource(LevelInfo::from_matrix(level));
+        <Perception as System>::setup(&mut Perception, &mut w.res);
+        let player = w
+            .create_entity()
+            .with(IsPlayer)
+            .with(HasPos(Pos { x: 5, y: 0 }))
+            .build();
+        let monster = w
+            .create_entity()
+            .with(HasPos(Pos { x: 8, y: 0 }))
+            .with(HasPerception::new(4))
+            .build();
+        (w, player, monster)
+    }
+
+    fn awareness(w: &World, e: Entity) -> Awareness {
+        w.read_storage::<HasPerception>().get(e).unwrap().awareness
+    }
+
+    fn move_to(w: &World, e: Entity, x: DimIndex) {
+        w.write_storage::<HasPos>()
+            .insert(e, HasPos(Pos { x, y: 0 }))
+            .unwrap();
+    }
+
+    #[test]
+    fn notices_player_within_sight_radius() {
+        let (w, player, monster) = create_world();
+        Perception.run_now(&w.res);
+        assert_eq!(awareness(&w, monster), Awareness::Sees(Pos { x: 5, y: 0 }));
+
+        move_to(&w, player, 2);
+        Perception.run_now(&w.res);
+        assert_eq!(
+            awareness(&w, monster),
+            Awareness::Remembers(Pos { x: 5, y: 0 })
+        );
+
+        move_to(&w, monster, 5);
+        Perception.run_now(&w.res);
+        assert_eq!(awareness(&w, monster), Awareness::Sees(Pos { x: 2, y: 0 }));
+    }
+
+    #[test]
+    fn forgets_player_at_last_seen_position() {
+        let (w, player, monster) = create_world();
+        Perception.run_now(&w.res);
+        move_to(&w, player, 12);
+        Perception.run_now(&w.res);
+        assert_eq!(
+            awareness(&w, monster),
+            Awareness::Remembers(Pos { x: 5, y: 0 })
+        );
+        move_to(&w, monster, 5);
+        move_to(&w, player, 15);
+        Perception.run_now(&w.res);
+        assert_eq!(awareness(&w, monster), Awareness::Unaware);
+    }
+
+    #[test]
+    fn doesnt_see_player_in_the_dark() {
+        let (w, _, monster) = create_world();
+        let dim = w.read_resource::<LevelInfo>().dim();
+        w.write_resource::<LightMap>().reset(dim);
+        Perception.run_now(&w.res);
+        assert_eq!(awareness(&w, monster), Awareness::Unaware);
+    }
+}
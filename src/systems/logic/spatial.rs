@@ -0,0 +1,129 @@
+use specs::prelude::*;
+use specs::storage::ComponentEvent;
+
+use crate::data::components::HasPos;
+use crate::data::structures::*;
+
+/// Keeps `SpatialIndex` in sync with `HasPos`, should run once positions settled and dead are
+/// removed. Everything is indexed on the first run and when the level is resized, after that
+/// only entities whose position was inserted, changed or removed are
+#[derive(Default)]
+pub struct IndexPositions {
+    changes: Option<ReaderId<ComponentEvent>>,
+    indexed: bool,
+}
+
+impl<'a> System<'a> for IndexPositions {
+    type SystemData = (
+        Read<'a, LevelInfo>,
+        Entities<'a>,
+        ReadStorage<'a, HasPos>,
+        Write<'a, SpatialIndex>,
+    );
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.changes = Some(WriteStorage::<HasPos>::fetch(res).register_reader());
+    }
+
+    fn run(&mut self, (level, entity, pos, mut index): <Self as System<'a>>::SystemData) {
+        let changes = self.changes.as_mut().expect("IndexPositions wasn't set up");
+        let changed = pos
+            .channel()
+            .read(changes)
+            .map(|event| match event {
+                ComponentEvent::Inserted(id)
+                | ComponentEvent::Modified(id)
+                | ComponentEvent::Removed(id) => *id,
+            })
+            .collect::<BitSet>();
+
+        if !self.indexed || index.dim() != level.dim() {
+            index.reset(level.dim());
+            for (e, pos) in (&entity, &pos).join() {
+                index.insert(pos.0, e);
+            }
+            self.indexed = true;
+            return;
+        }
+        for id in (&changed).join() {
+            let e = entity.entity(id);
+            match pos.get(e) {
+                Some(pos) if entity.is_alive(e) => index.insert(pos.0, e),
+                _ => index.remove(id),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Indexes everything from scratch, for tests which don't keep the system around
+    pub fn index_positions(w: &mut World) {
+        let mut s = IndexPositions::default();
+        <IndexPositions as System>::setup(&mut s, &mut w.res);
+        s.run_now(&w.res);
+    }
+
+    #[test]
+    fn indexes_current_positions() {
+        let mut w = World::new();
+        let mut s = IndexPositions::default();
+        <IndexPositions as System>::setup(&mut s, &mut w.res);
+        w.add_resource(LevelInfo::new(3, 3));
+
+        let pos = Pos { x: 1, y: 2 };
+        let e = w.create_entity().with(HasPos(pos)).build();
+        s.run_now(&w.res);
+        assert_eq!(w.read_resource::<SpatialIndex>().entities_at(pos), &[e]);
+
+        w.write_storage::<HasPos>()
+            .insert(e, HasPos(Pos { x: 0, y: 0 }))
+            .unwrap();
+        s.run_now(&w.res);
+        let index = w.read_resource::<SpatialIndex>();
+        assert!(index.entities_at(pos).is_empty());
+        assert_eq!(index.entities_at(Pos { x: 0, y: 0 }), &[e]);
+    }
+
+    #[test]
+    fn reindexes_only_moved_entities() {
+        let mut w = World::new();
+        let mut s = IndexPositions::default();
+        <IndexPositions as System>::setup(&mut s, &mut w.res);
+        w.add_resource(LevelInfo::new(3, 3));
+
+        let still = w.create_entity().with(HasPos(Pos { x: 0, y: 0 })).build();
+        let moving = w.create_entity().with(HasPos(Pos { x: 1, y: 1 })).build();
+        s.run_now(&w.res);
+        // Whatever isn't reported as changed is left as it was indexed
+        w.write_resource::<SpatialIndex>().remove(still.id());
+        w.write_storage::<HasPos>().get_mut(moving).unwrap().0 = Pos { x: 2, y: 2 };
+        s.run_now(&w.res);
+
+        let index = w.read_resource::<SpatialIndex>();
+        assert!(index.entities_at(Pos { x: 0, y: 0 }).is_empty());
+        assert!(index.entities_at(Pos { x: 1, y: 1 }).is_empty());
+        assert_eq!(index.entities_at(Pos { x: 2, y: 2 }), &[moving]);
+    }
+
+    #[test]
+    fn forgets_deleted_entities() {
+        let mut w = World::new();
+        let mut s = IndexPositions::default();
+        <IndexPositions as System>::setup(&mut s, &mut w.res);
+        w.add_resource(LevelInfo::new(3, 3));
+
+        let pos = Pos { x: 1, y: 1 };
+        let e = w.create_entity().with(HasPos(pos)).build();
+        s.run_now(&w.res);
+        w.delete_entity(e).unwrap();
+        s.run_now(&w.res);
+        assert!(w
+            .read_resource::<SpatialIndex>()
+            .entities_at(pos)
+            .is_empty());
+    }
+}
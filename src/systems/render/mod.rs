@@ -1,16 +1,22 @@
+use crate::common::fov::FovWindow;
 use crate::common::query::singleton;
 use crate::data::components::*;
-use crate::data::structures::matrix::Matrix;
+use crate::data::structures::bit_matrix::BitMatrix;
 use crate::data::structures::*;
 use log::warn;
 use specs::prelude::*;
 
 pub type Color = ::tcod::Color;
-pub const LIGHT_GROUND: Color = tcod::colors::GREY;
-pub const LIGHT_WALL: Color = tcod::colors::GREY;
+pub const CURSOR: Color = tcod::colors::YELLOW;
+/// Rows below the map used for the message log
+pub const LOG_LINES: DimIndex = 5;
 pub trait Renderable {
     fn color(&self) -> Color;
     fn display_char(&self) -> char;
+    /// Colour of the cell behind the character, `None` keeps whatever is there
+    fn background(&self) -> Option<Color> {
+        None
+    }
 }
 
 impl Renderable for IsVisible {
@@ -22,32 +28,35 @@ impl Renderable for IsVisible {
     }
 }
 
-impl Renderable for TileType {
+impl Renderable for LookCursor {
     fn color(&self) -> Color {
-        use self::TileType::*;
-
-        match self {
-            Wall => LIGHT_WALL,
-            RoomWall => LIGHT_WALL,
-            Ground => LIGHT_GROUND,
-        }
+        CURSOR
     }
+    fn display_char(&self) -> char {
+        'X'
+    }
+}
 
+impl Renderable for TileDef {
+    fn color(&self) -> Color {
+        self.fg
+    }
     fn display_char(&self) -> char {
-        use self::TileType::*;
-        match self {
-            Wall => '#',
-            RoomWall => '±',
-            Ground => '.',
-        }
+        self.glyph
+    }
+    fn background(&self) -> Option<Color> {
+        self.bg
     }
 }
 
 pub trait Renderer: Sized {
-    fn render<T>(&mut self, pos: Pos, r: &T, in_fov: bool)
+    /// `light` is what the tile is lit with when seen right now, `None` if only remembered
+    fn render<T>(&mut self, pos: Pos, r: &T, light: Option<Light>)
     where
         T: Renderable;
 
+    fn print(&mut self, pos: Pos, text: &str);
+
     fn clear(&mut self);
 
     fn as_specs_system(&mut self) -> RenderWrapper<Self> {
@@ -62,18 +71,23 @@ where
     R: Renderer,
 {
     type SystemData = (
-        ReadStorage<'a, HasPos>,
         ReadStorage<'a, IsVisible>,
         ReadStorage<'a, HasVision>,
         ReadStorage<'a, IsPlayer>,
+        ReadStorage<'a, IsItem>,
         Read<'a, LevelInfo>,
+        Read<'a, SpatialIndex>,
+        Read<'a, LightMap>,
+        Read<'a, LookCursor>,
+        Read<'a, MessageLog>,
     );
-    fn run(&mut self, (pos, vis, vision, is_pl, li): Self::SystemData) {
-        use specs::Join;
-
+    fn run(
+        &mut self,
+        (vis, vision, is_pl, items, li, index, light, cursor, log): Self::SystemData,
+    ) {
         let (player_vision, _) = singleton((&vision, &is_pl)).unwrap();
-        let pw: Option<&Matrix<bool>> = player_vision.fov();
-        let mem: Option<&Matrix<bool>> = player_vision.memory();
+        let pw: Option<&FovWindow> = player_vision.fov();
+        let mem: Option<&BitMatrix> = player_vision.memory();
 
         if let (Some(fov), Some(mem)) = (pw, mem) {
             let x: &mut R = &mut self.0;
@@ -81,14 +95,31 @@ where
             for (pos, vis) in li.all_cells() {
                 let show = mem[pos];
                 if show {
-                    x.render(pos, vis, fov[pos]);
+                    let seen = if fov[pos] {
+                        Some(light.light_at(pos))
+                    } else {
+                        None
+                    };
+                    x.render(pos, vis, seen);
                 }
             }
-            for (pos, vis) in (&pos, &vis).join() {
-                if fov[pos.0] {
-                    x.render(pos.0, vis, fov[pos.0]);
+            for pos in fov.ones() {
+                let mut here = index.entities_at(pos).to_vec();
+                // Whoever stands on an item is drawn over it
+                here.sort_by_key(|&e| items.get(e).is_none());
+                for e in here {
+                    if let Some(vis) = vis.get(e) {
+                        x.render(pos, vis, Some(light.light_at(pos)));
+                    }
                 }
             }
+            if let Some(pos) = cursor.0 {
+                x.render(pos, &*cursor, Some(Light::full()));
+            }
+            let lines = log.latest(LOG_LINES.into());
+            for (y, line) in (li.height()..).zip(lines) {
+                x.print(Pos { x: 0, y }, line);
+            }
         } else {
             warn!("Not found player FOV, not rendering");
         }
@@ -16,99 +16,165 @@ impl<'a> Validation<'a> for MoveValidation {
         ReadStorage<'a, HasPos>,
         ReadStorage<'a, IsPlayer>,
         ReadStorage<'a, IsFighter>,
-        Entities<'a>,
+        Read<'a, SpatialIndex>,
         Read<'a, LevelInfo>,
     );
 
-    fn run(&self, move_dir: Dir, (pos_storage, pl, tile, entity, level): Self::SD) -> Self::Output {
+    fn run(
+        &self,
+        move_dir: Dir,
+        (pos_storage, pl, fighters, index, level): Self::SD,
+    ) -> Self::Output {
         let target_pos: Pos = singleton((&pos_storage, &pl))
-            .map(|(ref mut player_pos, _)| player_pos.0 + move_dir)
-            .unwrap();
-        if !level.is_valid(target_pos) || !level[target_pos].is_walkable() {
+            .map(|(player_pos, _)| level.step(player_pos.0, move_dir))
+            .unwrap()?;
+        let tile = &level[target_pos];
+        if !tile.is_walkable() && !tile.is_openable() {
             return None;
         }
 
-        for (e, pos, _) in (&entity, &pos_storage, &tile).join() {
-            if pos.0 == target_pos {
-                return Some(ActorCommand::MeleeAttack {
-                    pos: target_pos,
-                    target: e,
-                });
-            }
+        match index.blocking_at(target_pos, &fighters) {
+            Some(target) => Some(ActorCommand::MeleeAttack {
+                pos: target_pos,
+                target,
+            }),
+            None => Some(ActorCommand::Move(move_dir)),
         }
-        Some(ActorCommand::Move(move_dir))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use specs::{Builder, World};
+    use specs::{Builder, RunNow, World};
     use tcod::colors::YELLOW;
 
-    use crate::data::structures::LevelInfo;
-    use crate::data::structures::Pos;
-    use crate::data::structures::TileType::*;
-    use crate::data::structures::{E, S};
+    use crate::data::structures::{E, N, S, SE, W};
 
     use super::*;
+    use crate::levels::ascii::AsciiMap;
     use crate::levels::races::orc_race;
+    use crate::systems::logic::spatial::tests::index_positions;
 
-    fn create_world(add_wall: bool) -> World {
+    /// World with the player and orcs standing where the map shows them
+    fn create_world(map: &str) -> (World, Vec<(Entity, Pos)>) {
+        let map = AsciiMap::parse(map).unwrap();
         let mut w = World::new();
-        let mut level = LevelInfo::new(1, 2);
-        level[Pos { x: 0, y: 0 }] = Ground;
-        level[Pos { x: 0, y: 1 }] = Ground;
-        if add_wall {
-            level[Pos { x: 0, y: 1 }] = Wall;
-        }
-        w.add_resource(level);
+        w.add_resource(map.level.clone());
         w.register::<HasVision>();
         w.register::<IsVisible>();
         w.register::<HasBrain>();
+        w.register::<HasPerception>();
+        w.register::<HasName>();
         MoveValidation::register(&mut w);
         w.create_entity()
             .is_player()
-            .with_actor_components('@', YELLOW, Pos { x: 0, y: 0 })
+            .with_actor_components('@', YELLOW, map.player_pos().unwrap())
             .build();
-        w
+        let orcs = map
+            .find("orc")
+            .map(|pos| {
+                let e = w
+                    .create_entity()
+                    .is_monster(&orc_race().to_template(), pos)
+                    .build();
+                (e, pos)
+            })
+            .collect();
+        index_positions(&mut w);
+        (w, orcs)
     }
 
     #[test]
     fn dont_allow_to_walk_into_wall() {
-        let mut w = create_world(true);
+        let (mut w, _) = create_world(
+            "
+            @
+            #
+            ",
+        );
         let result = MoveValidation.exec(S, &mut w);
         assert!(result.is_none());
     }
 
+    #[test]
+    fn allow_to_walk_into_closed_door_but_not_locked() {
+        let (mut w, _) = create_world(
+            "
+            @
+            +
+            ",
+        );
+        assert_eq!(MoveValidation.exec(S, &mut w), Some(ActorCommand::Move(S)));
+        let (mut w, _) = create_world(
+            "
+            + = locked_door
+            @
+            +
+            ",
+        );
+        assert!(MoveValidation.exec(S, &mut w).is_none());
+    }
+
     #[test]
     fn dont_allow_to_walk_out_of_bounds() {
-        let mut w = create_world(false);
+        let (mut w, _) = create_world(
+            "
+            @
+            .
+            ",
+        );
         let result = MoveValidation.exec(E, &mut w);
         assert!(result.is_none());
     }
 
+    #[test]
+    fn dont_allow_to_wrap_around_the_edge() {
+        let (mut w, _) = create_world(
+            "
+            @
+            .
+            ",
+        );
+        assert!(MoveValidation.exec(N, &mut w).is_none());
+        assert!(MoveValidation.exec(W, &mut w).is_none());
+    }
+
     #[test]
     fn converts_move_into_someone_to_atack() {
-        let mut w = create_world(false);
-        let target_pos = Pos { x: 0, y: 1 };
-        let e = w
-            .create_entity()
-            .is_monster(&orc_race().to_template(), target_pos)
-            .build();
+        let (mut w, orcs) = create_world(
+            "
+            @
+            o
+            ",
+        );
+        let (target, pos) = orcs[0];
         let result = MoveValidation.exec(S, &mut w).unwrap();
 
-        assert_eq!(
-            result,
-            ActorCommand::MeleeAttack {
-                pos: target_pos,
-                target: e
-            }
+        assert_eq!(result, ActorCommand::MeleeAttack { pos, target });
+    }
+
+    #[test]
+    fn converts_diagonal_move_into_someone_to_atack() {
+        let (mut w, orcs) = create_world(
+            "
+            @.
+            .o
+            ",
         );
+        let (target, pos) = orcs[0];
+        let result = MoveValidation.exec(SE, &mut w).unwrap();
+
+        assert_eq!(result, ActorCommand::MeleeAttack { pos, target });
     }
 
     #[test]
     fn allow_to_walk_on_the_ground() {
-        let mut w = create_world(false);
+        let (mut w, _) = create_world(
+            "
+            @
+            .
+            ",
+        );
         let result = MoveValidation.exec(S, &mut w);
         assert_eq!(Some(ActorCommand::Move(S)), result);
     }
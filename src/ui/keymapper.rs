@@ -15,6 +15,30 @@ impl Default for KeyMapper {
         game_commands.insert("ArrowRight".to_string(), Command::east());
         game_commands.insert("ArrowUp".to_string(), Command::north());
         game_commands.insert("ArrowDown".to_string(), Command::south());
+
+        game_commands.insert("Numpad4".to_string(), Command::west());
+        game_commands.insert("Numpad6".to_string(), Command::east());
+        game_commands.insert("Numpad8".to_string(), Command::north());
+        game_commands.insert("Numpad2".to_string(), Command::south());
+        game_commands.insert("Numpad7".to_string(), Command::north_west());
+        game_commands.insert("Numpad9".to_string(), Command::north_east());
+        game_commands.insert("Numpad1".to_string(), Command::south_west());
+        game_commands.insert("Numpad3".to_string(), Command::south_east());
+
+        game_commands.insert("KeyH".to_string(), Command::west());
+        game_commands.insert("KeyL".to_string(), Command::east());
+        game_commands.insert("KeyK".to_string(), Command::north());
+        game_commands.insert("KeyJ".to_string(), Command::south());
+        game_commands.insert("KeyY".to_string(), Command::north_west());
+        game_commands.insert("KeyU".to_string(), Command::north_east());
+        game_commands.insert("KeyB".to_string(), Command::south_west());
+        game_commands.insert("KeyN".to_string(), Command::south_east());
+
+        game_commands.insert("KeyO".to_string(), Command::explore());
+        game_commands.insert("KeyX".to_string(), Command::look());
+        game_commands.insert("Enter".to_string(), Command::confirm());
+        game_commands.insert("Period".to_string(), Command::descend());
+        game_commands.insert("Comma".to_string(), Command::ascend());
         KeyMapper { game_commands }
     }
 }
@@ -32,7 +56,31 @@ impl KeyMapper {
             Right => Some("ArrowRight"),
             Up => Some("ArrowUp"),
             Down => Some("ArrowDown"),
+            NumPad1 => Some("Numpad1"),
+            NumPad2 => Some("Numpad2"),
+            NumPad3 => Some("Numpad3"),
+            NumPad4 => Some("Numpad4"),
+            NumPad6 => Some("Numpad6"),
+            NumPad7 => Some("Numpad7"),
+            NumPad8 => Some("Numpad8"),
+            NumPad9 => Some("Numpad9"),
             Escape => Some("Escape"),
+            Enter | NumPadEnter => Some("Enter"),
+            Char => match key.printable {
+                'h' => Some("KeyH"),
+                'j' => Some("KeyJ"),
+                'k' => Some("KeyK"),
+                'l' => Some("KeyL"),
+                'y' => Some("KeyY"),
+                'u' => Some("KeyU"),
+                'b' => Some("KeyB"),
+                'n' => Some("KeyN"),
+                'o' => Some("KeyO"),
+                'x' => Some("KeyX"),
+                '>' | '.' => Some("Period"),
+                '<' | ',' => Some("Comma"),
+                _ => None,
+            },
             _ => None,
         };
         log::trace!("Maping for {:?} {:?}", key, mnem);
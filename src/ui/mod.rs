@@ -1,8 +1,16 @@
 use specs::prelude::*;
+use std::collections::HashSet;
+use std::mem;
 
+use crate::common::dijkstra::DijkstraMap;
+use crate::common::query::singleton;
 use crate::common::validations::Validation;
 use crate::data::components::*;
+use crate::data::structures::bit_matrix::BitMatrix;
+use crate::data::structures::pos::PosCollection;
 use crate::data::structures::*;
+use crate::levels::vaults::Vaults;
+use crate::levels::{level_at, populate};
 use crate::systems::logic::*;
 use crate::systems::render::Renderer;
 use crate::systems::validation::MoveValidation;
@@ -16,6 +24,7 @@ pub struct Game<'a, 'b> {
     pub key_mapper: KeyMapper,
     pub game_command_handler: GameCommandHandler,
     tick_dispatcher: Dispatcher<'a, 'b>,
+    index_positions: IndexPositions,
 }
 
 impl<'a, 'b> Game<'a, 'b> {
@@ -24,22 +33,30 @@ impl<'a, 'b> Game<'a, 'b> {
         let game_command_handler = GameCommandHandler;
 
         let mut tick_dispatcher = DispatcherBuilder::new()
-            .with(GetAiCommand, "ai_decide", &[])
+            .with(PlanActivities, "plan_activities", &[])
+            .with(Perception, "perception", &["plan_activities"])
+            .with(GetAiCommand, "ai_decide", &["perception"])
             .with(ExecuteCommands, "execute_commands", &["ai_decide"])
-            .with(Fov::default(), "fov", &[])
+            .with(Lighting::default(), "lighting", &["execute_commands"])
+            .with(Fov::default(), "fov", &["lighting"])
             .with(ExecuteEffects, "execute_damage", &["execute_commands"])
             .with(Clean, "remove_dead", &["execute_damage"])
             .build();
 
         tick_dispatcher.setup(&mut world.res);
         game_command_handler.setup(&mut world);
+        let mut index_positions = IndexPositions::default();
+        <IndexPositions as System>::setup(&mut index_positions, &mut world.res);
 
-        Game {
+        let mut game = Game {
             world,
             key_mapper,
             game_command_handler,
             tick_dispatcher,
-        }
+            index_positions,
+        };
+        game.index_positions();
+        game
     }
 
     pub fn render_on<R: Renderer>(&mut self, r: &mut R) {
@@ -50,6 +67,20 @@ impl<'a, 'b> Game<'a, 'b> {
     pub fn update(&mut self) {
         self.tick_dispatcher.dispatch(&self.world.res);
         self.world.maintain();
+        self.index_positions();
+    }
+
+    /// Player is in the middle of an activity and the game should tick without waiting for input
+    pub fn is_busy(&self) -> bool {
+        let (is_pl, activity): (ReadStorage<IsPlayer>, ReadStorage<HasActivity>) =
+            self.world.system_data();
+        (&is_pl, &activity).join().next().is_some()
+    }
+
+    /// Positions are indexed after `maintain` so removed entities are already gone
+    fn index_positions(&mut self) {
+        use specs::RunNow;
+        self.index_positions.run_now(&self.world.res);
     }
 }
 
@@ -58,20 +89,73 @@ pub struct GameCommandHandler;
 impl GameCommandHandler {
     pub fn setup(&self, res: &mut World) {
         res.register::<IsPlayer>();
+        res.register::<IsItem>();
+        res.register::<HasActivity>();
+        res.register::<Dormant>();
+        res.register::<HasName>();
+        res.res.entry::<Dungeon>().or_insert_with(Dungeon::default);
+        res.res.entry::<Vaults>().or_insert_with(Vaults::default);
+        res.add_resource(LookCursor::default());
+        res.add_resource(MessageLog::default());
     }
 
+    /// Returns whether the command took a turn, so the world should tick
     // FIXME(26) - Use validation framework for everyone here
-    pub fn exec(&self, gc: &Command, world: &mut World) {
+    pub fn exec(&self, gc: &Command, world: &mut World) -> bool {
+        let looking = world.read_resource::<LookCursor>().0.is_some();
         match gc {
+            Command::GameCommand(GameCommand::Exit) if looking => {
+                world.write_resource::<LookCursor>().0 = None;
+                false
+            }
             Command::GameCommand(GameCommand::Exit) => {
                 use std::process::exit;
                 exit(0);
             }
+            Command::GameCommand(GameCommand::Look) => {
+                let start = if looking {
+                    None
+                } else {
+                    Self::player_pos(world)
+                };
+                world.write_resource::<LookCursor>().0 = start;
+                false
+            }
+            Command::GameCommand(GameCommand::Confirm) => {
+                let target = world.write_resource::<LookCursor>().0.take();
+                match target {
+                    Some(target) => self.travel_to(target, world),
+                    None => false,
+                }
+            }
+            Command::GameCommand(GameCommand::Descend) => self.take_stairs(true, world),
+            Command::GameCommand(GameCommand::Ascend) => self.take_stairs(false, world),
+            Command::PlayerCommand(ActorCommand::Move(dir)) if looking => {
+                let (level, mut cursor): (Read<LevelInfo>, Write<LookCursor>) = world.system_data();
+                cursor.0 = cursor.0.map(|p| level.step(p, *dir).unwrap_or(p));
+                false
+            }
+            Command::PlayerActivity(activity) => {
+                Self::start_activity(activity.clone(), world);
+                true
+            }
             Command::PlayerCommand(ac) => {
                 use specs::RunNow;
                 // TODO(#26) Extract command handling from UI layer
                 let mut system: AssertUnique<IsPlayer> = Default::default();
                 system.run_now(&world.res);
+                // Manual commands take over whatever the player was busy with
+                world.exec(
+                    |(e, ispl, mut act): (
+                        Entities,
+                        ReadStorage<IsPlayer>,
+                        WriteStorage<HasActivity>,
+                    )| {
+                        for (e, _) in (&e, &ispl).join() {
+                            act.remove(e);
+                        }
+                    },
+                );
 
                 match ac {
                     ActorCommand::MeleeAttack { .. } => unreachable!(),
@@ -90,7 +174,475 @@ impl GameCommandHandler {
                         }
                     }
                 }
+                true
             }
         }
     }
+
+    fn player_pos(world: &World) -> Option<Pos> {
+        let (pos, ispl): (ReadStorage<HasPos>, ReadStorage<IsPlayer>) = world.system_data();
+        singleton((&pos, &ispl)).ok().map(|(p, _)| p.0)
+    }
+
+    fn start_activity(activity: Activity, world: &mut World) {
+        let (e, ispl, mut act): (Entities, ReadStorage<IsPlayer>, WriteStorage<HasActivity>) =
+            world.system_data();
+        for (e, _) in (&e, &ispl).join() {
+            act.insert(e, HasActivity(activity.clone())).unwrap();
+        }
+    }
+
+    /// Player standing on stairs goes to the level they lead to, which is generated on the first
+    /// visit. The level being left is kept in `Dungeon` with its entities put to sleep
+    fn take_stairs(&self, down: bool, world: &mut World) -> bool {
+        let (stairs, arrival) = if down {
+            (TileId::STAIRS_DOWN, TileId::STAIRS_UP)
+        } else {
+            (TileId::STAIRS_UP, TileId::STAIRS_DOWN)
+        };
+        let on_stairs = Self::player_pos(world).map_or(false, |p| {
+            world.read_resource::<LevelInfo>().tile(p) == stairs
+        });
+        let depth = world.read_resource::<Dungeon>().depth();
+        if !on_stairs || (!down && depth == 1) {
+            let message = if down {
+                "There are no stairs down here"
+            } else {
+                "There are no stairs up here"
+            };
+            world.write_resource::<MessageLog>().push(message);
+            return false;
+        }
+        let target = if down { depth + 1 } else { depth - 1 };
+        let known_start = world.read_resource::<Dungeon>().floor(target).map(|floor| {
+            floor
+                .level
+                .iter_pos()
+                .find(|&p| floor.level.tile(p) == arrival)
+        });
+        if known_start == Some(None) {
+            world
+                .write_resource::<MessageLog>()
+                .push("The stairs lead nowhere");
+            return false;
+        }
+
+        let memory = Self::leave_level(depth, world);
+        let level = mem::replace(
+            &mut *world.write_resource::<LevelInfo>(),
+            LevelInfo::default(),
+        );
+        let tiles = level.tiles().clone();
+        let restored = world
+            .write_resource::<Dungeon>()
+            .switch(Floor { level, memory }, target);
+        let start = match restored {
+            Some(floor) => {
+                *world.write_resource::<LevelInfo>() = floor.level;
+                Self::enter_level(target, floor.memory, world);
+                known_start
+                    .and_then(|start| start)
+                    .expect("GameCommandHandler::take_stairs")
+            }
+            None => {
+                let vaults = world.read_resource::<Vaults>().0.clone();
+                let (level, generated) = level_at(target, &mut rand::thread_rng(), tiles, vaults);
+                *world.write_resource::<LevelInfo>() = level;
+                populate(world, &generated);
+                generated.player_pos
+            }
+        };
+        Self::make_room(start, world);
+
+        let (ispl, mut pos, mut log): (
+            ReadStorage<IsPlayer>,
+            WriteStorage<HasPos>,
+            Write<MessageLog>,
+        ) = world.system_data();
+        for (pos, _) in (&mut pos, &ispl).join() {
+            pos.0 = start;
+        }
+        log.push(if down {
+            "You go down the stairs"
+        } else {
+            "You go up the stairs"
+        });
+        true
+    }
+
+    /// Moves whoever stands at `at` out of the way, to the closest free tile they can walk to.
+    /// Only the player is still where they were on the level they left, so they take no room
+    fn make_room(at: Pos, world: &mut World) {
+        let (e, level, ispl, fighter, mut pos): (
+            Entities,
+            Read<LevelInfo>,
+            ReadStorage<IsPlayer>,
+            ReadStorage<IsFighter>,
+            WriteStorage<HasPos>,
+        ) = world.system_data();
+        let blocking: Vec<Entity> = (&e, &pos, &fighter, !&ispl)
+            .join()
+            .filter(|(_, p, _, _)| p.0 == at)
+            .map(|(e, _, _, _)| e)
+            .collect();
+        if blocking.is_empty() {
+            return;
+        }
+        let mut taken: HashSet<Pos> = (&pos, !&ispl).join().map(|(p, _)| p.0).collect();
+        let distances = DijkstraMap::weighted(&*level, &at, |_, _| 1);
+        for e in blocking {
+            let free = level
+                .iter_pos()
+                .filter(|p| !taken.contains(p))
+                .filter_map(|p| distances.distance(p).map(|d| (d, p)))
+                .min();
+            match free {
+                Some((_, free)) => {
+                    log::info!("Entity {:?} makes room at stairs for {:?}", e, free);
+                    taken.insert(free);
+                    pos.insert(e, HasPos(free))
+                        .expect("GameCommandHandler::make_room");
+                }
+                None => log::warn!("No room for entity {:?} blocking stairs", e),
+            }
+        }
+    }
+
+    /// Puts everyone but the player to sleep on the level at `depth` and hands over what the
+    /// player remembers of it
+    fn leave_level(depth: Depth, world: &mut World) -> Option<BitMatrix> {
+        {
+            let (e, ispl, mut pos, mut dormant, mut plans, mut perception): (
+                Entities,
+                ReadStorage<IsPlayer>,
+                WriteStorage<HasPos>,
+                WriteStorage<Dormant>,
+                WriteStorage<PlansExecuting>,
+                WriteStorage<HasPerception>,
+            ) = world.system_data();
+            let left: Vec<(Entity, Pos)> = (&e, &pos, !&ispl)
+                .join()
+                .map(|(e, p, _)| (e, p.0))
+                .collect();
+            for (e, p) in left {
+                pos.remove(e);
+                plans.remove(e);
+                dormant
+                    .insert(e, Dormant { depth, pos: p })
+                    .expect("GameCommandHandler::leave_level");
+                if let Some(perception) = perception.get_mut(e) {
+                    perception.awareness = Awareness::Unaware;
+                }
+            }
+        }
+        let (e, ispl, mut act, mut vision): (
+            Entities,
+            ReadStorage<IsPlayer>,
+            WriteStorage<HasActivity>,
+            WriteStorage<HasVision>,
+        ) = world.system_data();
+        let mut memory = None;
+        for (e, _, vision) in (&e, &ispl, &mut vision).join() {
+            act.remove(e);
+            memory = vision.take_memory();
+        }
+        memory
+    }
+
+    /// Wakes up everyone sleeping on the level at `depth` and gives the player memory of it back
+    fn enter_level(depth: Depth, memory: Option<BitMatrix>, world: &mut World) {
+        let (e, ispl, level, mut pos, mut dormant, mut vision): (
+            Entities,
+            ReadStorage<IsPlayer>,
+            Read<LevelInfo>,
+            WriteStorage<HasPos>,
+            WriteStorage<Dormant>,
+            WriteStorage<HasVision>,
+        ) = world.system_data();
+        let woken: Vec<(Entity, Pos)> = (&e, &dormant)
+            .join()
+            .filter(|(_, d)| d.depth == depth)
+            .map(|(e, d)| (e, d.pos))
+            .collect();
+        for (e, p) in woken {
+            dormant.remove(e);
+            pos.insert(e, HasPos(p))
+                .expect("GameCommandHandler::enter_level");
+        }
+        let player = (&ispl, &mut vision).join().next();
+        if let (Some(memory), Some((_, vision))) = (memory, player) {
+            vision.restore_memory(level.id(), memory);
+        }
+    }
+
+    /// Only tiles the player has seen can be travelled to
+    fn travel_to(&self, target: Pos, world: &mut World) -> bool {
+        let known = {
+            let (level, vision, ispl): (
+                Read<LevelInfo>,
+                ReadStorage<HasVision>,
+                ReadStorage<IsPlayer>,
+            ) = world.system_data();
+            singleton((&vision, &ispl))
+                .ok()
+                .and_then(|(v, _)| v.memory())
+                .map_or(false, |mem| {
+                    mem[target] && (level[target].is_walkable() || level[target].is_openable())
+                })
+        };
+        if known {
+            Self::start_activity(Activity::travel(target), world);
+        } else {
+            world
+                .write_resource::<MessageLog>()
+                .push("You don't know the way there");
+        }
+        known
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tcod::colors::YELLOW;
+
+    use crate::data::structures::matrix::Matrix;
+    use crate::levels::races::orc_race;
+
+    use super::*;
+
+    fn create_game<'a, 'b>() -> Game<'a, 'b> {
+        let mut world = World::new();
+        let level = Matrix::tabulate(
+            Dim {
+                width: 30,
+                height: 1,
+            },
+            |_| TileId::GROUND,
+        );
+        world.add_resource(LevelInfo::from_matrix(level));
+        world.register::<IsVisible>();
+        let mut game = Game::new(world);
+        game.world
+            .create_entity()
+            .is_player()
+            .with(EmitsLight::torch())
+            .with_actor_components('@', YELLOW, Pos { x: 0, y: 0 })
+            .build();
+        game.update();
+        game
+    }
+
+    #[test]
+    fn look_mode_moves_cursor_instead_of_player() {
+        let mut game = create_game();
+        let handler = GameCommandHandler;
+        assert!(!handler.exec(&Command::look(), &mut game.world));
+        assert!(!handler.exec(&Command::east(), &mut game.world));
+        assert_eq!(
+            game.world.read_resource::<LookCursor>().0,
+            Some(Pos { x: 1, y: 0 })
+        );
+        assert!(!handler.exec(&Command::exit(), &mut game.world));
+        assert_eq!(game.world.read_resource::<LookCursor>().0, None);
+    }
+
+    #[test]
+    fn travels_to_confirmed_tile() {
+        let mut game = create_game();
+        let handler = GameCommandHandler;
+        handler.exec(&Command::look(), &mut game.world);
+        handler.exec(&Command::east(), &mut game.world);
+        handler.exec(&Command::east(), &mut game.world);
+        assert!(handler.exec(&Command::confirm(), &mut game.world));
+        while game.is_busy() {
+            game.update();
+        }
+        assert_eq!(
+            GameCommandHandler::player_pos(&game.world),
+            Some(Pos { x: 2, y: 0 })
+        );
+    }
+
+    #[test]
+    fn refuses_to_travel_to_unknown_tile() {
+        let mut game = create_game();
+        let handler = GameCommandHandler;
+        game.world.write_resource::<LookCursor>().0 = Some(Pos { x: 29, y: 0 });
+        assert!(!handler.exec(&Command::confirm(), &mut game.world));
+        assert!(!game.is_busy());
+        let log = game.world.read_resource::<MessageLog>();
+        assert_eq!(
+            log.latest(1).collect::<Vec<_>>(),
+            vec!["You don't know the way there"]
+        );
+    }
+
+    fn player_memory(game: &Game) -> BitMatrix {
+        let (vision, ispl): (ReadStorage<HasVision>, ReadStorage<IsPlayer>) =
+            game.world.system_data();
+        singleton((&vision, &ispl))
+            .ok()
+            .and_then(|(v, _)| v.memory().cloned())
+            .unwrap()
+    }
+
+    #[test]
+    fn stairs_lead_to_levels_kept_as_they_were() {
+        let mut game = create_game();
+        let handler = GameCommandHandler;
+        let stairs = Pos { x: 1, y: 0 };
+        game.world
+            .write_resource::<LevelInfo>()
+            .set(stairs, TileId::STAIRS_DOWN);
+        let lamp = game
+            .world
+            .create_entity()
+            .is_lamp(Pos { x: 20, y: 0 }, 1)
+            .build();
+        let first = game.world.read_resource::<LevelInfo>().id();
+        handler.exec(&Command::east(), &mut game.world);
+        game.update();
+        let memory = player_memory(&game);
+
+        assert!(handler.exec(&Command::descend(), &mut game.world));
+        game.update();
+        assert_eq!(game.world.read_resource::<Dungeon>().depth(), 2);
+        assert_ne!(game.world.read_resource::<LevelInfo>().id(), first);
+        assert_eq!(game.world.read_storage::<HasPos>().get(lamp), None);
+        assert_eq!(
+            game.world.read_storage::<Dormant>().get(lamp),
+            Some(&Dormant {
+                depth: 1,
+                pos: Pos { x: 20, y: 0 }
+            })
+        );
+        let arrival = GameCommandHandler::player_pos(&game.world).unwrap();
+        assert_eq!(
+            game.world.read_resource::<LevelInfo>().tile(arrival),
+            TileId::STAIRS_UP
+        );
+
+        assert!(handler.exec(&Command::ascend(), &mut game.world));
+        game.update();
+        assert_eq!(game.world.read_resource::<Dungeon>().depth(), 1);
+        assert_eq!(game.world.read_resource::<LevelInfo>().id(), first);
+        assert_eq!(GameCommandHandler::player_pos(&game.world), Some(stairs));
+        assert_eq!(
+            game.world.read_storage::<HasPos>().get(lamp),
+            Some(&HasPos(Pos { x: 20, y: 0 }))
+        );
+        assert_eq!(player_memory(&game), memory);
+    }
+
+    #[test]
+    fn monster_on_arrival_stairs_makes_room() {
+        let mut game = create_game();
+        let handler = GameCommandHandler;
+        let stairs = Pos { x: 1, y: 0 };
+        game.world
+            .write_resource::<LevelInfo>()
+            .set(stairs, TileId::STAIRS_DOWN);
+        let orc = game
+            .world
+            .create_entity()
+            .is_monster(&orc_race().to_template(), Pos { x: 25, y: 0 })
+            .build();
+        handler.exec(&Command::east(), &mut game.world);
+        game.update();
+        assert!(handler.exec(&Command::descend(), &mut game.world));
+        game.update();
+        game.world
+            .write_storage::<Dormant>()
+            .insert(
+                orc,
+                Dormant {
+                    depth: 1,
+                    pos: stairs,
+                },
+            )
+            .unwrap();
+
+        assert!(handler.exec(&Command::ascend(), &mut game.world));
+        assert_eq!(GameCommandHandler::player_pos(&game.world), Some(stairs));
+        let orc_pos = game.world.read_storage::<HasPos>().get(orc).unwrap().0;
+        assert_ne!(orc_pos, stairs);
+        assert_eq!(orc_pos.chebyshev_distance(stairs), 1);
+    }
+
+    #[test]
+    fn room_is_made_on_a_tile_reachable_from_stairs() {
+        let mut world = World::new();
+        let level = Matrix::tabulate(
+            Dim {
+                width: 3,
+                height: 3,
+            },
+            |p| {
+                if p.x == 1 {
+                    TileId::WALL
+                } else {
+                    TileId::GROUND
+                }
+            },
+        );
+        world.add_resource(LevelInfo::from_matrix(level));
+        world.register::<HasPos>();
+        world.register::<IsPlayer>();
+        world.register::<IsFighter>();
+        let stairs = Pos { x: 2, y: 0 };
+        let orc = world
+            .create_entity()
+            .with(HasPos(stairs))
+            .with(IsFighter::new(1, 1, 1))
+            .build();
+        world
+            .create_entity()
+            .with(HasPos(Pos { x: 2, y: 1 }))
+            .build();
+        // Where the player stood on the level they left
+        world
+            .create_entity()
+            .with(IsPlayer)
+            .with(HasPos(Pos { x: 2, y: 2 }))
+            .build();
+
+        GameCommandHandler::make_room(stairs, &mut world);
+        assert_eq!(
+            world.read_storage::<HasPos>().get(orc),
+            Some(&HasPos(Pos { x: 2, y: 2 }))
+        );
+    }
+
+    #[test]
+    fn setup_keeps_existing_dungeon() {
+        let mut game = create_game();
+        let mut dungeon = Dungeon::default();
+        dungeon.switch(
+            Floor {
+                level: LevelInfo::default(),
+                memory: None,
+            },
+            2,
+        );
+        game.world.add_resource(dungeon);
+        GameCommandHandler.setup(&mut game.world);
+        assert_eq!(game.world.read_resource::<Dungeon>().depth(), 2);
+    }
+
+    #[test]
+    fn stairs_are_taken_only_when_standing_on_them() {
+        let mut game = create_game();
+        let handler = GameCommandHandler;
+        assert!(!handler.exec(&Command::descend(), &mut game.world));
+        assert!(!handler.exec(&Command::ascend(), &mut game.world));
+        assert_eq!(game.world.read_resource::<Dungeon>().depth(), 1);
+        let log = game.world.read_resource::<MessageLog>();
+        assert_eq!(
+            log.latest(2).collect::<Vec<_>>(),
+            vec![
+                "There are no stairs down here",
+                "There are no stairs up here"
+            ]
+        );
+    }
 }